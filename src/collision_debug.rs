@@ -0,0 +1,232 @@
+//! A debug overlay showing how balls collide, drawn with gizmos.
+//!
+//! The [`Action::CollisionDebugToggle`] key (F3 unless rebound) shows the
+//! [`Aabb2d`] of every collider and the [`BoundingCircle`] and velocity of
+//! every ball, the way `check_for_collisions` sees them, along with the point
+//! of the nearest box closest to each ball, as `ball_collision` works it out.
+//! Every bounce stays marked for a few frames with the side of the box that
+//! was picked, the normal the ball was reflected off and the ball's velocity
+//! before and after. A hint naming the key is only shown alongside the
+//! stepping tooling, when the game is built with the `bevy_debug_stepping`
+//! feature.
+
+use bevy::{
+    math::bounding::{Aabb2d, BoundingCircle, BoundingVolume},
+    prelude::*,
+};
+
+use crate::{
+    input::{Action, Actions, InputBindings},
+    state::simulating,
+    Ball, Collider, Collision, CollisionEvent, KillZone, Velocity,
+};
+
+/// How many frames a bounce stays on screen
+const RECENT_CONTACT_FRAMES: u32 = 30;
+/// Velocity arrows are as long as the distance travelled in this many seconds
+const VELOCITY_ARROW_SECONDS: f32 = 0.1;
+const NORMAL_ARROW_LENGTH: f32 = 30.0;
+const CLOSEST_POINT_SIZE: f32 = 4.0;
+
+const COLLIDER_COLOR: Color = Color::srgb(0.2, 0.8, 0.2);
+const KILL_ZONE_COLOR: Color = Color::srgb(0.9, 0.2, 0.2);
+const BALL_COLOR: Color = Color::srgb(0.2, 0.6, 1.0);
+const CLOSEST_POINT_COLOR: Color = Color::srgb(1.0, 0.6, 0.0);
+const SIDE_COLOR: Color = Color::srgb(1.0, 0.0, 1.0);
+const NORMAL_COLOR: Color = Color::srgb(1.0, 1.0, 0.0);
+const VELOCITY_BEFORE_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const VELOCITY_COLOR: Color = Color::srgb(0.0, 0.9, 0.9);
+
+const HINT_FONT_SIZE: f32 = 15.0;
+const HINT_COLOR: Color = Color::srgb(0.2, 0.2, 0.2);
+
+/// Plugin adding the collision overlay, hidden until it is toggled on
+pub struct CollisionDebugPlugin;
+
+impl Plugin for CollisionDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_gizmo_config(
+            CollisionGizmos,
+            GizmoConfig {
+                enabled: false,
+                ..default()
+            },
+        )
+        .init_resource::<RecentContacts>()
+        // Colliders are looked up before `break_bricks` despawns the broken bricks
+        .add_systems(
+            FixedUpdate,
+            record_contacts
+                .after(crate::check_for_collisions)
+                .before(crate::break_bricks)
+                .run_if(simulating),
+        )
+        .add_systems(
+            Update,
+            (toggle_overlay, (draw_colliders, draw_balls, draw_contacts)).chain(),
+        );
+        if cfg!(feature = "bevy_debug_stepping") {
+            app.add_systems(Startup, build_hint);
+        }
+    }
+}
+
+/// Gizmos of the collision overlay, which is toggled by enabling or disabling them
+#[derive(Default, Reflect, GizmoConfigGroup)]
+struct CollisionGizmos;
+
+/// A bounce that is still being shown
+struct RecentContact {
+    event: CollisionEvent,
+    /// Ends of the side of the collider that was hit
+    side: (Vec2, Vec2),
+    frames_left: u32,
+}
+
+#[derive(Resource, Default)]
+struct RecentContacts(Vec<RecentContact>);
+
+/// Bounding box of a collider, as `check_for_collisions` builds it
+fn bounding_box(transform: &Transform) -> Aabb2d {
+    Aabb2d::new(
+        transform.translation.truncate(),
+        transform.scale.truncate() / 2.,
+    )
+}
+
+/// Ends of the side of `bounding_box` facing `side`
+fn side_edge(bounding_box: Aabb2d, side: Collision) -> (Vec2, Vec2) {
+    let Aabb2d { min, max } = bounding_box;
+    match side {
+        Collision::Left => (min, Vec2::new(min.x, max.y)),
+        Collision::Right => (Vec2::new(max.x, min.y), max),
+        Collision::Top => (Vec2::new(min.x, max.y), max),
+        Collision::Bottom => (min, Vec2::new(max.x, min.y)),
+    }
+}
+
+fn build_hint(mut commands: Commands, bindings: Res<InputBindings>) {
+    // Just above the stepping hint, see the `stepping` module
+    commands.spawn((
+        Text::new(format!(
+            "Press {} to toggle the collision overlay",
+            bindings.describe(Action::CollisionDebugToggle)
+        )),
+        TextFont {
+            font_size: HINT_FONT_SIZE,
+            ..default()
+        },
+        TextColor(HINT_COLOR),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(25.0),
+            left: Val::Px(5.0),
+            ..default()
+        },
+    ));
+}
+
+fn toggle_overlay(actions: Res<Actions>, mut config_store: ResMut<GizmoConfigStore>) {
+    if actions.just_pressed(Action::CollisionDebugToggle) {
+        let (config, _) = config_store.config_mut::<CollisionGizmos>();
+        config.enabled = !config.enabled;
+    }
+}
+
+fn record_contacts(
+    mut collision_events: EventReader<CollisionEvent>,
+    colliders: Query<&Transform>,
+    mut recent: ResMut<RecentContacts>,
+) {
+    for event in collision_events.read() {
+        let Ok(transform) = colliders.get(event.collider) else {
+            continue;
+        };
+        recent.0.push(RecentContact {
+            event: *event,
+            side: side_edge(bounding_box(transform), event.side),
+            frames_left: RECENT_CONTACT_FRAMES,
+        });
+    }
+}
+
+fn draw_colliders(
+    mut gizmos: Gizmos<CollisionGizmos>,
+    colliders: Query<(&Transform, Has<KillZone>), Or<(With<Collider>, With<KillZone>)>>,
+) {
+    for (transform, is_kill_zone) in &colliders {
+        let color = if is_kill_zone {
+            KILL_ZONE_COLOR
+        } else {
+            COLLIDER_COLOR
+        };
+        let bounding_box = bounding_box(transform);
+        gizmos.rect_2d(bounding_box.center(), bounding_box.half_size() * 2., color);
+    }
+}
+
+fn draw_balls(
+    mut gizmos: Gizmos<CollisionGizmos>,
+    balls: Query<(&Transform, &Velocity), With<Ball>>,
+    colliders: Query<&Transform, (With<Collider>, Without<Ball>)>,
+) {
+    for (transform, velocity) in &balls {
+        let ball = BoundingCircle::new(transform.translation.truncate(), transform.scale.x / 2.);
+        let center = ball.center();
+        gizmos.circle_2d(center, ball.radius(), BALL_COLOR);
+        gizmos.arrow_2d(
+            center,
+            center + **velocity * VELOCITY_ARROW_SECONDS,
+            VELOCITY_COLOR,
+        );
+
+        let closest = colliders
+            .iter()
+            .map(|collider| bounding_box(collider).closest_point(center))
+            .min_by(|a, b| {
+                a.distance_squared(center)
+                    .total_cmp(&b.distance_squared(center))
+            });
+        if let Some(closest) = closest {
+            gizmos.line_2d(center, closest, CLOSEST_POINT_COLOR);
+            gizmos.cross_2d(closest, CLOSEST_POINT_SIZE, CLOSEST_POINT_COLOR);
+        }
+    }
+}
+
+/// Draw the recent bounces, fading them out as they age
+fn draw_contacts(mut gizmos: Gizmos<CollisionGizmos>, mut recent: ResMut<RecentContacts>) {
+    for contact in &recent.0 {
+        let alpha = contact.frames_left as f32 / RECENT_CONTACT_FRAMES as f32;
+        let CollisionEvent {
+            point,
+            normal,
+            velocity_before,
+            velocity_after,
+            ..
+        } = contact.event;
+
+        let (start, end) = contact.side;
+        gizmos.line_2d(start, end, SIDE_COLOR.with_alpha(alpha));
+        gizmos.arrow_2d(
+            point,
+            point + normal * NORMAL_ARROW_LENGTH,
+            NORMAL_COLOR.with_alpha(alpha),
+        );
+        gizmos.arrow_2d(
+            point - velocity_before * VELOCITY_ARROW_SECONDS,
+            point,
+            VELOCITY_BEFORE_COLOR.with_alpha(alpha),
+        );
+        gizmos.arrow_2d(
+            point,
+            point + velocity_after * VELOCITY_ARROW_SECONDS,
+            VELOCITY_COLOR.with_alpha(alpha),
+        );
+    }
+
+    recent.0.retain_mut(|contact| {
+        contact.frames_left -= 1;
+        contact.frames_left > 0
+    });
+}
@@ -0,0 +1,495 @@
+//! Tuning values for the game, loaded from `assets/breakout.config.ron`.
+//!
+//! Every field is optional: anything left out of the file keeps the value the
+//! game was designed with, see [`GameConfig::default`]. Colors are sRGB
+//! triples, as in the level files.
+//!
+//! ```ron
+//! (
+//!     paddle: (speed: 650.0, color: (0.2, 0.6, 0.3)),
+//!     ball: (max_bounce_angle: 45.0),
+//! )
+//! ```
+//!
+//! Single values can also be overridden from the command line with
+//! `--set section.field=value`, where `value` is written as it would be in the
+//! file, and a different file can be picked with `--config <asset path>`.
+//!
+//! With the `hot_reload` feature the file is watched while the game runs, and
+//! changes are picked up straight away. Brick sizes and the arena's shape only
+//! apply to the bricks of the next level.
+
+use bevy::{
+    asset::{io::Reader, AssetLoadFailedEvent, AssetLoader, LoadContext, LoadState},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::difficulty::Difficulty;
+
+/// Config file loaded at startup unless `--config` says otherwise, relative to the `assets` directory
+pub const DEFAULT_CONFIG_PATH: &str = "breakout.config.ron";
+
+/// Plugin loading the [`GameConfig`] and keeping it up to date with its file
+pub struct ConfigPlugin {
+    /// Asset path of the config file
+    pub path: String,
+    /// Values set on the command line, which win over the file
+    pub overrides: Vec<ConfigOverride>,
+    /// Values to use as they are instead of loading the file, as replays do
+    pub fixed: Option<GameConfig>,
+}
+
+impl Default for ConfigPlugin {
+    fn default() -> Self {
+        ConfigPlugin {
+            path: DEFAULT_CONFIG_PATH.to_string(),
+            overrides: Vec::new(),
+            fixed: None,
+        }
+    }
+}
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<GameConfig>()
+            .init_asset_loader::<ConfigLoader>();
+
+        if let Some(fixed) = &self.fixed {
+            app.insert_resource(fixed.clone());
+            return;
+        }
+
+        // Start from the defaults so that the game can run before the file is loaded, or without it
+        let overrides = ConfigOverrides(self.overrides.clone());
+        let config = overrides.apply(&GameConfig::default());
+
+        app.insert_resource(config)
+            .insert_resource(overrides)
+            .insert_resource(ConfigPath(self.path.clone()))
+            .add_systems(Startup, load_config)
+            .add_systems(Update, update_config);
+    }
+}
+
+/// All the values tuning the feel of the game
+#[derive(Asset, Resource, TypePath, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub paddle: PaddleConfig,
+    pub ball: BallConfig,
+    pub arena: ArenaConfig,
+    pub bricks: BrickConfig,
+    /// How the balls speed up at each difficulty
+    pub speed_up: SpeedUpPresets,
+    pub scoring: ScoringConfig,
+    /// Balls the player may lose before the game is over
+    pub lives: usize,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            paddle: PaddleConfig::default(),
+            ball: BallConfig::default(),
+            arena: ArenaConfig::default(),
+            bricks: BrickConfig::default(),
+            speed_up: SpeedUpPresets::default(),
+            scoring: ScoringConfig::default(),
+            lives: 3,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Height of the paddle's center
+    pub fn paddle_y(&self) -> f32 {
+        self.arena.bottom + self.paddle.gap_to_floor
+    }
+}
+
+// These values are defined in `Transform` units.
+// Using the default 2D camera they correspond 1:1 with screen pixels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaddleConfig {
+    /// Width and height of the paddle, before any power-ups
+    pub size: Vec2,
+    pub speed: f32,
+    /// How close can the paddle get to the wall
+    pub padding: f32,
+    pub gap_to_floor: f32,
+    pub color: (f32, f32, f32),
+}
+
+impl Default for PaddleConfig {
+    fn default() -> Self {
+        PaddleConfig {
+            size: Vec2::new(120.0, 20.0),
+            speed: 500.0,
+            padding: 10.0,
+            gap_to_floor: 60.0,
+            color: (0.3, 0.3, 0.7),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BallConfig {
+    pub diameter: f32,
+    pub speed: f32,
+    /// Where a served ball sits on the paddle, as a distance from its center.
+    /// Off center, the ball is launched at an angle like a bounce from that spot.
+    pub serve_offset: f32,
+    /// Angle from vertical of a ball bouncing off the very edge of the paddle, in degrees.
+    /// Keeping this well below 90° stops the ball from travelling almost horizontally.
+    pub max_bounce_angle: f32,
+    pub color: (f32, f32, f32),
+}
+
+impl Default for BallConfig {
+    fn default() -> Self {
+        BallConfig {
+            diameter: 30.0,
+            speed: 400.0,
+            serve_offset: 20.0,
+            max_bounce_angle: 60.0,
+            color: (1.0, 0.5, 0.5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArenaConfig {
+    /// x coordinate of the left wall's center
+    pub left: f32,
+    /// x coordinate of the right wall's center
+    pub right: f32,
+    /// y coordinate of the floor's center
+    pub bottom: f32,
+    /// y coordinate of the ceiling's center
+    pub top: f32,
+    pub wall_thickness: f32,
+    pub wall_color: (f32, f32, f32),
+    pub background_color: (f32, f32, f32),
+}
+
+impl Default for ArenaConfig {
+    fn default() -> Self {
+        ArenaConfig {
+            left: -450.0,
+            right: 450.0,
+            bottom: -300.0,
+            top: 300.0,
+            wall_thickness: 10.0,
+            wall_color: (0.8, 0.8, 0.8),
+            background_color: (0.9, 0.9, 0.9),
+        }
+    }
+}
+
+// The brick layout itself comes from the level files in `assets/levels`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrickConfig {
+    pub size: Vec2,
+    // These values are exact
+    pub gap: f32,
+    pub gap_to_ceiling: f32,
+    // These values are lower bounds; levels that don't respect them are reported when spawned
+    pub gap_to_paddle: f32,
+    pub gap_to_sides: f32,
+    /// How far a nearly broken brick fades towards the background, from 0 to 1
+    pub damage_fade: f32,
+}
+
+impl Default for BrickConfig {
+    fn default() -> Self {
+        BrickConfig {
+            size: Vec2::new(100.0, 30.0),
+            gap: 5.0,
+            gap_to_ceiling: 20.0,
+            gap_to_paddle: 270.0,
+            gap_to_sides: 20.0,
+            damage_fade: 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeedUpPresets {
+    pub easy: SpeedUpConfig,
+    pub normal: SpeedUpConfig,
+    pub hard: SpeedUpConfig,
+}
+
+impl Default for SpeedUpPresets {
+    fn default() -> Self {
+        SpeedUpPresets {
+            easy: SpeedUpConfig {
+                start: 0.85,
+                max: 1.4,
+                paddle_hits: 8,
+                per_paddle_hits: 0.05,
+                top_row: 0.1,
+                ceiling: 0.1,
+                per_second: 0.002,
+            },
+            normal: SpeedUpConfig::default(),
+            hard: SpeedUpConfig {
+                start: 1.15,
+                max: 2.2,
+                paddle_hits: 4,
+                per_paddle_hits: 0.08,
+                top_row: 0.2,
+                ceiling: 0.2,
+                per_second: 0.006,
+            },
+        }
+    }
+}
+
+impl SpeedUpPresets {
+    pub fn get(&self, difficulty: Difficulty) -> &SpeedUpConfig {
+        match difficulty {
+            Difficulty::Easy => &self.easy,
+            Difficulty::Normal => &self.normal,
+            Difficulty::Hard => &self.hard,
+        }
+    }
+}
+
+// Speeds are multiples of `ball.speed`, and go back to `start` whenever a ball is served
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeedUpConfig {
+    pub start: f32,
+    pub max: f32,
+    /// The balls speed up every time the paddle has been hit this many times, or never if 0
+    pub paddle_hits: u32,
+    pub per_paddle_hits: f32,
+    /// Speed-up the first time a ball hits the top row of bricks
+    pub top_row: f32,
+    /// Speed-up the first time a ball touches the ceiling
+    pub ceiling: f32,
+    /// Speed-up for every second a ball is in play
+    pub per_second: f32,
+}
+
+impl Default for SpeedUpConfig {
+    fn default() -> Self {
+        SpeedUpConfig {
+            start: 1.0,
+            max: 1.75,
+            paddle_hits: 6,
+            per_paddle_hits: 0.05,
+            top_row: 0.15,
+            ceiling: 0.15,
+            per_second: 0.004,
+        }
+    }
+}
+
+// Bricks are worth the points of their kind plus those of their row, times the combo multiplier
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoringConfig {
+    /// Extra points for a brick in each row, top row first. Rows further down get none.
+    pub row_points: Vec<usize>,
+    /// Bricks the ball has to hit without touching the paddle to raise the multiplier by one
+    pub combo_step: u32,
+    pub max_multiplier: u32,
+    /// Clearing a level in less than this many seconds earns a bonus
+    pub par_time: f32,
+    /// Bonus for every second under `par_time`
+    pub time_bonus: usize,
+    /// Bonus for every life left when a level is cleared
+    pub life_bonus: usize,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        ScoringConfig {
+            row_points: vec![3, 2, 1],
+            combo_step: 3,
+            max_multiplier: 5,
+            par_time: 120.0,
+            time_bonus: 1,
+            life_bonus: 10,
+        }
+    }
+}
+
+/// Turn a color from the config into a [`Color`]
+pub fn srgb((red, green, blue): (f32, f32, f32)) -> Color {
+    Color::srgb(red, green, blue)
+}
+
+/// A single value set from the command line with `--set section.field=value`
+#[derive(Debug, Clone)]
+pub struct ConfigOverride {
+    /// Field names leading to the value, outermost first
+    path: Vec<String>,
+    value: ron::Value,
+}
+
+impl std::str::FromStr for ConfigOverride {
+    type Err = ConfigError;
+
+    fn from_str(setting: &str) -> Result<Self, ConfigError> {
+        let Some((path, value)) = setting.split_once('=') else {
+            return Err(ConfigError::MissingValue(setting.to_string()));
+        };
+        Ok(ConfigOverride {
+            path: path.trim().split('.').map(str::to_string).collect(),
+            value: ron::from_str(value)?,
+        })
+    }
+}
+
+impl std::fmt::Display for ConfigOverride {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.join("."))
+    }
+}
+
+/// Possible errors produced by [`ConfigLoader`] and by command-line overrides
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// An [IO](std::io) Error
+    #[error("could not read config file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON](ron) Error
+    #[error("could not parse config: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    /// An override with nothing to set
+    #[error("`{0}` should look like `section.field=value`")]
+    MissingValue(String),
+    /// An override naming a field the config doesn't have
+    #[error("there is no setting called `{0}`")]
+    UnknownSetting(String),
+    /// An override giving a value of the wrong type
+    #[error("`{setting}` can't be set to that: {error}")]
+    InvalidValue { setting: String, error: ron::Error },
+}
+
+/// The overrides given on the command line, applied every time the file is (re)loaded
+#[derive(Resource)]
+struct ConfigOverrides(Vec<ConfigOverride>);
+
+impl ConfigOverrides {
+    /// Return `config` with the overrides applied, skipping any that don't fit
+    fn apply(&self, config: &GameConfig) -> GameConfig {
+        let mut config = config.clone();
+        for setting in &self.0 {
+            match setting.apply(&config) {
+                Ok(overridden) => config = overridden,
+                Err(error) => error!("{error}"),
+            }
+        }
+        config
+    }
+}
+
+impl ConfigOverride {
+    fn apply(&self, config: &GameConfig) -> Result<GameConfig, ConfigError> {
+        // Go through RON's dynamic representation so that any field can be reached by name
+        let mut root: ron::Value = ron::from_str(
+            &ron::to_string(config).expect("the config can always be written as RON"),
+        )?;
+
+        let mut target = &mut root;
+        for field in &self.path {
+            let ron::Value::Map(map) = target else {
+                return Err(ConfigError::UnknownSetting(self.to_string()));
+            };
+            target = map
+                .iter_mut()
+                .find(|(key, _)| **key == ron::Value::String(field.clone()))
+                .map(|(_, value)| value)
+                .ok_or_else(|| ConfigError::UnknownSetting(self.to_string()))?;
+        }
+        *target = self.value.clone();
+
+        root.into_rust().map_err(|error| ConfigError::InvalidValue {
+            setting: self.to_string(),
+            error,
+        })
+    }
+}
+
+#[derive(Resource, Deref)]
+struct ConfigPath(String);
+
+#[derive(Resource, Deref)]
+pub struct ConfigHandle(Handle<GameConfig>);
+
+#[derive(Default)]
+struct ConfigLoader;
+
+impl AssetLoader for ConfigLoader {
+    type Asset = GameConfig;
+    type Settings = ();
+    type Error = ConfigError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<GameConfig, ConfigError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["config.ron"]
+    }
+}
+
+fn load_config(mut commands: Commands, asset_server: Res<AssetServer>, path: Res<ConfigPath>) {
+    commands.insert_resource(ConfigHandle(asset_server.load(path.0.clone())));
+}
+
+/// Whether the config file has finished loading, successfully or not.
+/// Always true when there is no file to load.
+pub fn config_loaded(asset_server: Res<AssetServer>, handle: Option<Res<ConfigHandle>>) -> bool {
+    handle.is_none_or(|handle| {
+        matches!(
+            asset_server.load_state(&**handle),
+            LoadState::Loaded | LoadState::Failed(_)
+        )
+    })
+}
+
+/// Copy the config file into [`GameConfig`] whenever it is (re)loaded
+fn update_config(
+    mut asset_events: EventReader<AssetEvent<GameConfig>>,
+    mut load_failures: EventReader<AssetLoadFailedEvent<GameConfig>>,
+    handle: Res<ConfigHandle>,
+    path: Res<ConfigPath>,
+    configs: Res<Assets<GameConfig>>,
+    overrides: Res<ConfigOverrides>,
+    mut config: ResMut<GameConfig>,
+) {
+    for failure in load_failures.read() {
+        // The previous values are kept, so a typo while tuning doesn't stop the game
+        error!("{}, keeping the current settings", failure.error);
+    }
+
+    for event in asset_events.read() {
+        if !event.is_loaded_with_dependencies(&**handle) && !event.is_modified(&**handle) {
+            continue;
+        }
+        if let Some(loaded) = configs.get(&**handle) {
+            info!("loaded config from {}", **path);
+            *config = overrides.apply(loaded);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! How fast the balls go, and how that changes as a level is played.
+//!
+//! Every served ball starts at the [`Difficulty`]'s starting speed and speeds
+//! up each time the paddle has been hit a number of times, the first time a
+//! ball reaches the top row of bricks or the ceiling, and little by little
+//! while it is in play, up to a maximum. How much is tuned per difficulty in
+//! the config's `speed_up` section, and levels can scale all of it with their
+//! `speed_scale`. The difficulty is picked with `--difficulty easy|normal|hard`.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::GameConfig,
+    level::{LevelSpeedScale, StartLevel},
+    state::{simulating, InGame},
+    Ball, CollisionEvent, Hit, LifeLost, StuckToPaddle, WallLocation,
+};
+
+/// Plugin keeping track of the [`BallSpeed`]
+pub struct DifficultyPlugin {
+    pub difficulty: Difficulty,
+}
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.difficulty)
+            .init_resource::<BallSpeed>()
+            .add_systems(OnEnter(InGame), reset_ball_speed)
+            .add_systems(StartLevel, reset_ball_speed)
+            .add_systems(
+                FixedUpdate,
+                (
+                    update_ball_speed.before(crate::move_paddle),
+                    count_speed_ups
+                        .after(crate::check_for_collisions)
+                        .before(crate::break_bricks),
+                    reset_ball_speed
+                        .run_if(on_event::<LifeLost>)
+                        .after(crate::check_for_ball_lost),
+                )
+                    .run_if(simulating),
+            );
+    }
+}
+
+/// How hard the game is, picking one of the config's `speed_up` presets
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Every difficulty, from the easiest
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("there is no difficulty called `{name}`")),
+        }
+    }
+}
+
+/// Speed of every ball in play, and what it has gained since the last serve
+#[derive(Resource, Debug, Default)]
+pub struct BallSpeed {
+    /// In pixels per second, before any power-ups
+    pub current: f32,
+    /// Speed gained since the last serve, as a multiple of `ball.speed`
+    gained: f32,
+    paddle_hits: u32,
+    reached_top_row: bool,
+    reached_ceiling: bool,
+}
+
+impl BallSpeed {
+    /// The current speed as a multiple of the config's `ball.speed`
+    pub fn multiple(&self, config: &GameConfig) -> f32 {
+        self.current / config.ball.speed
+    }
+}
+
+/// Go back to the starting speed whenever a ball is served
+fn reset_ball_speed(mut speed: ResMut<BallSpeed>) {
+    *speed = BallSpeed {
+        current: speed.current,
+        ..default()
+    };
+}
+
+/// Speed up on the hits that call for it
+fn count_speed_ups(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut speed: ResMut<BallSpeed>,
+    difficulty: Res<Difficulty>,
+    config: Res<GameConfig>,
+) {
+    let speed_up = config.speed_up.get(*difficulty);
+    for event in collision_events.read() {
+        match event.hit {
+            Hit::Paddle => {
+                speed.paddle_hits += 1;
+                if speed_up.paddle_hits > 0
+                    && speed.paddle_hits.is_multiple_of(speed_up.paddle_hits)
+                {
+                    speed.gained += speed_up.per_paddle_hits;
+                }
+            }
+            Hit::Wall(WallLocation::Top) if !speed.reached_ceiling => {
+                speed.reached_ceiling = true;
+                speed.gained += speed_up.ceiling;
+            }
+            Hit::Brick { row: 0, .. } if !speed.reached_top_row => {
+                speed.reached_top_row = true;
+                speed.gained += speed_up.top_row;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Work out this step's speed, speeding up a little while any ball is moving
+pub fn update_ball_speed(
+    mut speed: ResMut<BallSpeed>,
+    balls: Query<(), (With<Ball>, Without<StuckToPaddle>)>,
+    difficulty: Res<Difficulty>,
+    level_speed_scale: Res<LevelSpeedScale>,
+    config: Res<GameConfig>,
+    time: Res<Time>,
+) {
+    let speed_up = config.speed_up.get(*difficulty);
+    if !balls.is_empty() {
+        speed.gained += speed_up.per_second * time.delta_secs();
+    }
+    let multiple = (speed_up.start + speed.gained).min(speed_up.max);
+    speed.current = config.ball.speed * multiple * **level_speed_scale;
+}
@@ -0,0 +1,242 @@
+//! Running the game without a window, audio device or real clock.
+//!
+//! `--headless` simulates a fixed number of `FixedUpdate` ticks as fast as it
+//! can and prints where the game ended up, including a checksum of the balls
+//! and bricks. Every frame advances time by exactly one fixed timestep, and the
+//! random parts of the game are seeded (with 0 unless `--seed` says otherwise),
+//! so the same arguments always give the same output. The paddle is steered by
+//! a simple autopilot that chases the lowest ball, unless `--replay` feeds in
+//! the commands of a recorded game, see the `replay` module. A replay is played
+//! to its end unless `--ticks` stops it sooner, and only checked against the
+//! recording if it was played to the end. Levels are moved on from as soon as
+//! they are cleared.
+//!
+//! ```text
+//! cargo run -- --headless --ticks 6400 --seed 7 --record run.replay.ron
+//! cargo run -- --headless --replay run.replay.ron
+//! ```
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::PluginsState, input::InputPlugin, log::LogPlugin, prelude::*, state::app::StatesPlugin,
+    text::TextPlugin, time::TimeUpdateStrategy,
+};
+
+use crate::{
+    config::config_loaded,
+    difficulty::Difficulty,
+    input::{read_actions, update_actions, Action, Actions, PaddleCommand, PaddleInput},
+    level::CurrentLevel,
+    replay::{save_recording, Playback, Recording},
+    rng::RngSeed,
+    state::{simulating, GameState},
+    Ball, BreakoutPlugin, Lives, Paddle, Score, StuckToPaddle,
+};
+
+/// How many fixed steps to simulate unless `--ticks` or a replay says otherwise:
+/// a minute of play at 64 Hz
+pub const DEFAULT_TICKS: u32 = 64 * 60;
+
+/// How long to wait for the levels and the config before giving up
+const LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Simulate `steps` fixed steps of play, or a whole replay, and print the outcome
+pub fn run(mut breakout: BreakoutPlugin, steps: Option<u32>) -> AppExit {
+    breakout.seed.get_or_insert(0);
+    let replayed = breakout
+        .replay
+        .as_ref()
+        .map(|replay| (replay.steps, replay.checksum));
+    let steps = steps
+        .or(replayed.map(|(steps, _)| steps))
+        .unwrap_or(DEFAULT_TICKS);
+    // A replay can't be played for longer than it was recorded
+    let steps = replayed.map_or(steps, |(replayed_steps, _)| steps.min(replayed_steps));
+
+    let mut app = App::new();
+    app.add_plugins(LogPlugin::default());
+    add_game(&mut app, breakout);
+    if !start_playing(&mut app) {
+        error!("gave up waiting for the levels and config to load");
+        return AppExit::error();
+    }
+    play(&mut app, steps);
+    app.world_mut()
+        .run_system_cached(save_recording)
+        .expect("saving the recording doesn't fail to run");
+
+    let world = app.world();
+    let recording = world.resource::<Recording>();
+    println!("steps: {}", recording.steps);
+    println!("seed: {}", **world.resource::<RngSeed>());
+    println!("difficulty: {:?}", world.resource::<Difficulty>());
+    println!("state: {:?}", world.resource::<State<GameState>>().get());
+    println!("level: {}", **world.resource::<CurrentLevel>() + 1);
+    println!("score: {}", **world.resource::<Score>());
+    println!("lives: {}", **world.resource::<Lives>());
+    println!("checksum: {:016x}", recording.checksum);
+
+    if let Some((replayed_steps, checksum)) = replayed {
+        if recording.steps == steps && steps < replayed_steps {
+            println!("replay: not checked, only {steps} of its {replayed_steps} steps were played");
+        } else if recording.steps == replayed_steps && recording.checksum == checksum {
+            println!("replay: matches");
+        } else {
+            println!("replay: diverged, the recording ended after {replayed_steps} steps with checksum {checksum:016x}");
+            return AppExit::error();
+        }
+    }
+
+    app.should_exit().unwrap_or(AppExit::Success)
+}
+
+/// Add the game to `app`, without a window and with time advancing by exactly one
+/// fixed timestep per update
+pub fn add_game(app: &mut App, breakout: BreakoutPlugin) {
+    app.add_plugins((
+        MinimalPlugins,
+        // Changes on disk mustn't affect a run that is meant to be repeatable
+        AssetPlugin {
+            watch_for_changes_override: Some(false),
+            ..default()
+        },
+        StatesPlugin,
+        InputPlugin,
+        // Text is still laid out, it just never gets drawn
+        TextPlugin,
+    ))
+    // Likewise for the balls' mesh and material
+    .init_asset::<Mesh>()
+    .init_asset::<ColorMaterial>()
+    .init_asset::<Image>()
+    .init_asset::<TextureAtlasLayout>()
+    .add_plugins(breakout)
+    .insert_resource(TimeUpdateStrategy::ManualDuration(
+        Time::<Fixed>::default().timestep(),
+    ))
+    .add_systems(
+        FixedUpdate,
+        autopilot
+            .in_set(PaddleInput)
+            .after(read_actions)
+            .run_if(simulating.and(not(resource_exists::<Playback>))),
+    )
+    .add_systems(PreUpdate, continue_after_level.after(update_actions));
+}
+
+/// Wait for the levels and the config to load, then start a game.
+///
+/// Returns `false` if they didn't load in time.
+pub fn start_playing(app: &mut App) -> bool {
+    // What `App::run` would otherwise do before the first update
+    while app.plugins_state() == PluginsState::Adding {
+        bevy::tasks::tick_global_task_pools_on_main_thread();
+    }
+    app.finish();
+    app.cleanup();
+
+    // Loading takes however long it takes, so only start counting once everything is ready
+    let started = Instant::now();
+    while !is_loaded(app) {
+        if started.elapsed() > LOAD_TIMEOUT {
+            return false;
+        }
+        app.update();
+        thread::sleep(Duration::from_millis(1));
+    }
+    // Let `config::update_config` pick up the loaded file
+    app.update();
+    app.update();
+
+    app.world_mut()
+        .resource_mut::<NextState<GameState>>()
+        .set(GameState::Playing);
+    true
+}
+
+/// Update `app` until `steps` fixed steps of the game have been recorded, or the game stops
+pub fn play(app: &mut App, steps: u32) {
+    // Nothing is simulated in the frames between levels, so allow for a few more frames than steps
+    let max_frames = u64::from(steps) * 2 + 64;
+    for frame in 0..max_frames {
+        // The request to start playing only goes through on the first update
+        if frame > 0 {
+            let world = app.world();
+            let in_play = matches!(
+                world.resource::<State<GameState>>().get(),
+                GameState::Playing | GameState::LevelComplete
+            );
+            if world.resource::<Recording>().steps >= steps || !in_play {
+                break;
+            }
+        }
+        app.update();
+    }
+}
+
+/// How far the paddle may be off the ball before the autopilot moves it
+const AUTOPILOT_DEADZONE: f32 = 5.0;
+
+/// Keep the paddle under the lowest ball, and launch any ball that is stuck to it
+fn autopilot(
+    mut command: ResMut<PaddleCommand>,
+    paddle_transform: Single<&Transform, With<Paddle>>,
+    balls: Query<(&Transform, Has<StuckToPaddle>), With<Ball>>,
+) {
+    let launch = balls.iter().any(|(_, is_stuck)| is_stuck);
+    let offset = balls
+        .iter()
+        .map(|(transform, _)| transform.translation)
+        .min_by(|a, b| a.y.total_cmp(&b.y))
+        .map_or(0.0, |lowest| lowest.x - paddle_transform.translation.x);
+    let direction = if offset.abs() > AUTOPILOT_DEADZONE {
+        offset.signum()
+    } else {
+        0.0
+    };
+    *command = PaddleCommand {
+        direction,
+        launch,
+        target: None,
+    };
+}
+
+/// Move on from a cleared level straight away, as there is nobody to confirm it
+fn continue_after_level(state: Res<State<GameState>>, mut actions: ResMut<Actions>) {
+    if *state.get() == GameState::LevelComplete {
+        actions.press(Action::Confirm);
+    }
+}
+
+/// Whether the campaign and the config file have both finished loading
+fn is_loaded(app: &mut App) -> bool {
+    let menu_reached = *app.world().resource::<State<GameState>>() == GameState::MainMenu;
+    menu_reached
+        && app
+            .world_mut()
+            .run_system_cached(config_loaded)
+            .unwrap_or(false)
+}
+
+/// A headless game with the default config, ready to [`play`], for tests
+#[cfg(test)]
+pub fn test_app(seed: u64, replay: Option<crate::replay::Replay>) -> App {
+    let mut app = App::new();
+    add_game(
+        &mut app,
+        BreakoutPlugin {
+            config_path: crate::config::DEFAULT_CONFIG_PATH.to_string(),
+            overrides: Vec::new(),
+            seed: Some(seed),
+            difficulty: Difficulty::default(),
+            record: None,
+            replay,
+        },
+    );
+    assert!(start_playing(&mut app), "the levels and config should load");
+    app
+}
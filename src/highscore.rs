@@ -0,0 +1,459 @@
+//! The high-score table, kept on disk between sessions.
+//!
+//! Scores are saved as RON in the platform's data directory (for example
+//! `~/.local/share/mygame/highscores.ron` on Linux). The file carries a format
+//! version so that older tables can be upgraded rather than thrown away.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    input::{Action, Actions, MenuInput, RawInput},
+    level::CurrentLevel,
+    menu::{menu, spawn_menu_item, MenuBack, MenuPress},
+    state::{screen_root, screen_text, screen_title, GameState},
+    Score,
+};
+
+/// How many scores the table keeps
+const HIGH_SCORE_COUNT: usize = 10;
+const MAX_NAME_LENGTH: usize = 12;
+const DEFAULT_NAME: &str = "Player";
+/// Characters the d-pad cycles through when picking a name without a keyboard
+const PICKER_CHARACTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 ";
+/// Current version of the high score file format
+const FILE_VERSION: u32 = 1;
+const FILE_NAME: &str = "highscores.ron";
+
+const TABLE_FONT_SIZE: f32 = 22.0;
+const TABLE_TEXT_COLOR: Color = Color::srgb(0.2, 0.2, 0.4);
+const TABLE_HIGHLIGHT_COLOR: Color = Color::srgb(0.9, 0.35, 0.35);
+
+/// Plugin loading and saving the high-score table, and showing it from the
+/// main menu and when the game is over
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_high_scores)
+            .add_systems(OnEnter(GameState::HighScores), spawn_high_scores_screen)
+            .add_systems(OnEnter(GameState::GameOver), spawn_game_over_screen)
+            .add_systems(OnExit(GameState::GameOver), stop_name_entry)
+            .add_systems(
+                Update,
+                (
+                    leave_game_over.run_if(not(resource_exists::<NameEntry>)),
+                    enter_name.run_if(resource_exists::<NameEntry>),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::GameOver)),
+            );
+    }
+}
+
+/// One line of the high-score table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub score: usize,
+    /// The level the player had got to, counting from 1
+    pub level: usize,
+    /// Day the score was set, as `YYYY-MM-DD`
+    pub date: String,
+}
+
+/// The best scores so far, highest first
+#[derive(Resource, Debug, Default)]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+    /// Where the table is saved, or `None` if it must not be written to
+    path: Option<PathBuf>,
+}
+
+impl HighScores {
+    /// Whether `score` is good enough to make it into the table
+    pub fn qualifies(&self, score: usize) -> bool {
+        score > 0
+            && (self.entries.len() < HIGH_SCORE_COUNT
+                || self.entries.iter().any(|entry| score > entry.score))
+    }
+
+    /// Add a score to the table, returning its position if it made it in.
+    /// Ties are ranked below the scores that were set earlier.
+    fn insert(&mut self, high_score: HighScore) -> Option<usize> {
+        let rank = self
+            .entries
+            .iter()
+            .position(|entry| high_score.score > entry.score)
+            .unwrap_or(self.entries.len());
+        if rank >= HIGH_SCORE_COUNT {
+            return None;
+        }
+        self.entries.insert(rank, high_score);
+        self.entries.truncate(HIGH_SCORE_COUNT);
+        Some(rank)
+    }
+
+    fn save(&self) -> Result<(), HighScoreError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = HighScoreFile {
+            version: FILE_VERSION,
+            entries: self.entries.clone(),
+        };
+        let contents = ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())?;
+
+        // Write to a temporary file first so that a crash can't leave a half-written table behind
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let temporary = path.with_extension("ron.tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+/// Possible errors while reading or writing the high-score file
+#[derive(Debug, Error)]
+enum HighScoreError {
+    #[error("could not access the high score file: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not parse the high score file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not write the high score file: {0}")]
+    Write(#[from] ron::Error),
+    #[error(
+        "the high score file has version {0}, but only versions up to {FILE_VERSION} are known"
+    )]
+    UnknownVersion(u32),
+}
+
+/// Just the version of a high score file, read before the rest of it
+#[derive(Deserialize)]
+struct FileVersion {
+    version: u32,
+}
+
+/// On-disk layout of the current [`FILE_VERSION`].
+/// When this changes, keep the old layout around and convert it in [`read_high_scores`].
+#[derive(Serialize, Deserialize)]
+struct HighScoreFile {
+    version: u32,
+    entries: Vec<HighScore>,
+}
+
+fn high_score_path() -> Option<PathBuf> {
+    Some(
+        dirs::data_dir()?
+            .join(env!("CARGO_PKG_NAME"))
+            .join(FILE_NAME),
+    )
+}
+
+/// Read a high score file of any known version
+fn read_high_scores(path: &Path) -> Result<Vec<HighScore>, HighScoreError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        // No scores have been saved yet
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+
+    match ron::from_str::<FileVersion>(&contents)?.version {
+        FILE_VERSION => Ok(ron::from_str::<HighScoreFile>(&contents)?.entries),
+        version => Err(HighScoreError::UnknownVersion(version)),
+    }
+}
+
+fn load_high_scores(mut commands: Commands) {
+    let Some(path) = high_score_path() else {
+        warn!("no data directory found, high scores won't be saved");
+        commands.insert_resource(HighScores::default());
+        return;
+    };
+
+    let high_scores = match read_high_scores(&path) {
+        Ok(entries) => HighScores {
+            entries,
+            path: Some(path),
+        },
+        // Leave a file we can't make sense of alone rather than overwrite someone's scores
+        Err(error) => {
+            error!("{error}; high scores won't be saved this session");
+            HighScores::default()
+        }
+    };
+    commands.insert_resource(high_scores);
+}
+
+/// Today's date as `YYYY-MM-DD`, in UTC
+fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let days = (seconds / 86_400) as i64;
+
+    // Convert days since 1970-01-01 to a civil date,
+    // see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// The name being typed in for a new high score
+#[derive(Resource, Default)]
+struct NameEntry(String);
+
+impl NameEntry {
+    fn push(&mut self, character: char) {
+        if self.0.chars().count() < MAX_NAME_LENGTH {
+            self.0.push(character);
+        }
+    }
+
+    /// Turn the last character to the next (or previous) one of [`PICKER_CHARACTERS`],
+    /// starting a first one if the name is empty
+    fn cycle_last(&mut self, forward: bool) {
+        let count = PICKER_CHARACTERS.chars().count();
+        let last = self.0.pop();
+        let index = last
+            .and_then(|last| {
+                PICKER_CHARACTERS
+                    .chars()
+                    .position(|character| character == last.to_ascii_uppercase())
+            })
+            .map_or(if forward { 0 } else { count - 1 }, |index| {
+                if forward {
+                    (index + 1) % count
+                } else {
+                    (index + count - 1) % count
+                }
+            });
+        self.0.extend(PICKER_CHARACTERS.chars().nth(index));
+    }
+}
+
+/// Holds whatever the game over screen is currently showing below its title
+#[derive(Component)]
+struct GameOverContent;
+
+/// Shows what is being typed in for [`NameEntry`]
+#[derive(Component)]
+struct NameEntryText;
+
+fn back_to_main_menu<E: Event>(_: Trigger<E>, mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::MainMenu);
+}
+
+fn spawn_high_scores_screen(mut commands: Commands, high_scores: Res<HighScores>) {
+    commands
+        .spawn(screen_root(GameState::HighScores))
+        .with_children(|p| {
+            p.spawn(screen_title("High scores"));
+            spawn_high_score_table(p, &high_scores.entries, None);
+            p.spawn(menu())
+                .observe(back_to_main_menu::<MenuBack>)
+                .with_children(|p| {
+                    spawn_menu_item(p, "Back").observe(back_to_main_menu::<MenuPress>);
+                });
+        });
+}
+
+fn spawn_game_over_screen(mut commands: Commands, high_scores: Res<HighScores>, score: Res<Score>) {
+    let new_record = high_scores.qualifies(**score);
+    if new_record {
+        commands.init_resource::<NameEntry>();
+    }
+
+    commands
+        .spawn(screen_root(GameState::GameOver))
+        .with_children(|p| {
+            p.spawn(screen_title("Game over"));
+            let mut content = p.spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(20.0),
+                    ..default()
+                },
+                GameOverContent,
+            ));
+            if new_record {
+                content.with_children(|p| {
+                    p.spawn(screen_text(format!(
+                        "New high score: {}! Type your name, or pick it with the d-pad, and press Enter",
+                        **score
+                    )));
+                    p.spawn((screen_text("_"), NameEntryText));
+                });
+            } else {
+                content.with_children(|p| {
+                    spawn_high_score_table(p, &high_scores.entries, None);
+                    p.spawn(screen_text("Press Enter to return to the menu"));
+                });
+            }
+        });
+}
+
+/// Lay out the high-score table as a grid, optionally highlighting one of its rows
+pub fn spawn_high_score_table(
+    parent: &mut ChildBuilder,
+    entries: &[HighScore],
+    highlight: Option<usize>,
+) {
+    if entries.is_empty() {
+        parent.spawn(screen_text("No high scores yet"));
+        return;
+    }
+
+    parent
+        .spawn(Node {
+            display: Display::Grid,
+            grid_template_columns: RepeatedGridTrack::auto(5),
+            column_gap: Val::Px(30.0),
+            row_gap: Val::Px(4.0),
+            ..default()
+        })
+        .with_children(|p| {
+            let cells = |rank: usize, entry: &HighScore| {
+                [
+                    format!("{}.", rank + 1),
+                    entry.name.clone(),
+                    entry.score.to_string(),
+                    format!("level {}", entry.level),
+                    entry.date.clone(),
+                ]
+            };
+            for (rank, entry) in entries.iter().enumerate() {
+                let color = if highlight == Some(rank) {
+                    TABLE_HIGHLIGHT_COLOR
+                } else {
+                    TABLE_TEXT_COLOR
+                };
+                for cell in cells(rank, entry) {
+                    p.spawn((
+                        Text::new(cell),
+                        TextFont {
+                            font_size: TABLE_FONT_SIZE,
+                            ..default()
+                        },
+                        TextColor(color),
+                    ));
+                }
+            }
+        });
+}
+
+/// Type in a name for the new high score, then save it and show the table.
+///
+/// Without a keyboard, up and down change the last letter, right adds another
+/// and left takes one away, see [`RawInput::menu_input`]. [`Action::Confirm`]
+/// finishes the name either way.
+#[allow(clippy::too_many_arguments)]
+fn enter_name(
+    mut commands: Commands,
+    mut keyboard_events: EventReader<KeyboardInput>,
+    input: RawInput,
+    actions: Res<Actions>,
+    mut name_entry: ResMut<NameEntry>,
+    mut high_scores: ResMut<HighScores>,
+    score: Res<Score>,
+    current_level: Res<CurrentLevel>,
+    name_text: Single<&mut Text, With<NameEntryText>>,
+    content: Single<Entity, With<GameOverContent>>,
+) {
+    let mut confirmed = actions.just_pressed(Action::Confirm);
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Enter => confirmed = true,
+            Key::Backspace => {
+                name_entry.0.pop();
+            }
+            Key::Space => name_entry.push(' '),
+            Key::Character(characters) => {
+                for character in characters.chars() {
+                    if !character.is_control() {
+                        name_entry.push(character);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    match input.menu_input() {
+        Some(MenuInput::Up) => name_entry.cycle_last(true),
+        Some(MenuInput::Down) => name_entry.cycle_last(false),
+        Some(MenuInput::Right) => name_entry.push('A'),
+        Some(MenuInput::Left) => {
+            name_entry.0.pop();
+        }
+        Some(MenuInput::Select | MenuInput::Back) | None => (),
+    }
+
+    if !confirmed {
+        name_text.into_inner().0 = format!("{}_", name_entry.0);
+        return;
+    }
+
+    let name = name_entry.0.trim();
+    let rank = high_scores.insert(HighScore {
+        name: if name.is_empty() { DEFAULT_NAME } else { name }.to_string(),
+        score: **score,
+        level: **current_level + 1,
+        date: today(),
+    });
+    if let Err(error) = high_scores.save() {
+        error!("{error}");
+    }
+    commands.remove_resource::<NameEntry>();
+
+    // Swap the prompt for the updated table
+    commands
+        .entity(*content)
+        .despawn_descendants()
+        .with_children(|p| {
+            spawn_high_score_table(p, &high_scores.entries, rank);
+            p.spawn(screen_text("Press Enter to return to the menu"));
+        });
+}
+
+fn leave_game_over(actions: Res<Actions>, mut next_state: ResMut<NextState<GameState>>) {
+    if actions.just_pressed(Action::Confirm) {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+fn stop_name_entry(mut commands: Commands) {
+    commands.remove_resource::<NameEntry>();
+}
@@ -0,0 +1,825 @@
+//! What the player wants to do, whatever it came from.
+//!
+//! Keys, mouse buttons, touches and gamepad buttons and sticks are bound to
+//! [`Action`]s, and the rest of the game reads the [`Actions`] rather than
+//! any particular device. The bindings are saved as RON in the platform's config
+//! directory (for example `~/.config/mygame/bindings.ron` on Linux), and can be
+//! changed from the controls screen, reached from the settings screen. The mouse
+//! cursor or a touch can also steer the paddle directly.
+//!
+//! Gameplay systems read the [`PaddleCommand`] for the current fixed step
+//! rather than the [`Actions`]. The `replay` module records these commands, and
+//! can feed them back in place of the player's.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    ecs::{schedule::Stepping, system::SystemParam},
+    input::{touch::Touch, InputSystem},
+    prelude::*,
+    utils::HashSet,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::state::{screen_root, screen_text, screen_title, GameState, PauseMenu};
+
+/// Current version of the bindings file format
+const FILE_VERSION: u32 = 1;
+const FILE_NAME: &str = "bindings.ron";
+
+/// How far a stick has to be pushed before it counts at all
+const STICK_DEADZONE: f32 = 0.2;
+/// How far a stick has to be pushed to count as a button press
+const STICK_PRESS_THRESHOLD: f32 = 0.5;
+
+const CONTROLS_FONT_SIZE: f32 = 22.0;
+const CONTROLS_TEXT_COLOR: Color = Color::srgb(0.2, 0.2, 0.4);
+const CONTROLS_HIGHLIGHT_COLOR: Color = Color::srgb(0.9, 0.35, 0.35);
+/// Bindings shared with another action
+const CONTROLS_SHARED_COLOR: Color = Color::srgb(0.8, 0.5, 0.1);
+
+/// Plugin mapping the player's input to [`Actions`], and turning them into a
+/// [`PaddleCommand`] every fixed step
+pub struct PaddleInputPlugin;
+
+impl Plugin for PaddleInputPlugin {
+    fn build(&self, app: &mut App) {
+        // Loaded straight away so that startup systems can describe the bindings
+        app.insert_resource(InputBindings::load())
+            .init_resource::<Actions>()
+            .init_resource::<PaddleCommand>()
+            .init_resource::<ControlsMenu>()
+            .add_systems(PreUpdate, update_actions.after(InputSystem))
+            .add_systems(FixedUpdate, read_actions.in_set(PaddleInput))
+            .add_systems(OnEnter(GameState::Playing), forget_launch)
+            .add_systems(
+                OnEnter(GameState::Controls),
+                spawn_controls_screen(GameState::Controls),
+            )
+            .add_systems(
+                OnEnter(PauseMenu::Controls),
+                spawn_controls_screen(PauseMenu::Controls),
+            )
+            .add_systems(
+                Update,
+                (
+                    navigate_controls,
+                    update_controls_screen.run_if(
+                        resource_changed::<InputBindings>.or(resource_changed::<ControlsMenu>),
+                    ),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Controls).or(in_state(PauseMenu::Controls))),
+            );
+    }
+}
+
+/// Something the player can do, whichever input it is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    /// Send off any balls stuck to the paddle
+    Launch,
+    /// Start a game, or move on from a screen
+    Confirm,
+    Pause,
+    /// Give up the current game from the pause screen
+    QuitToMenu,
+    SteppingToggle,
+    /// Run the next system while stepping
+    SteppingStep,
+    /// Run the rest of the frame while stepping
+    SteppingContinue,
+    /// Log the state of stepping
+    SteppingDump,
+    /// Select the previous system in the stepping list
+    SteppingSelectPrevious,
+    /// Select the next system in the stepping list
+    SteppingSelectNext,
+    /// Set or clear a breakpoint on the selected system while stepping
+    SteppingBreakpoint,
+    /// Make the selected system skip or stop skipping while stepping
+    SteppingSkip,
+    /// Make the selected system run on every frame or stop doing so while stepping
+    SteppingAlwaysRun,
+    /// Show or hide the collision overlay, see the `collision_debug` module
+    CollisionDebugToggle,
+}
+
+impl Action {
+    /// Every action, in the order the controls screen lists them
+    pub const ALL: [Action; 16] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Launch,
+        Action::Confirm,
+        Action::Pause,
+        Action::QuitToMenu,
+        Action::SteppingToggle,
+        Action::SteppingStep,
+        Action::SteppingContinue,
+        Action::SteppingDump,
+        Action::SteppingSelectPrevious,
+        Action::SteppingSelectNext,
+        Action::SteppingBreakpoint,
+        Action::SteppingSkip,
+        Action::SteppingAlwaysRun,
+        Action::CollisionDebugToggle,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Launch => "Launch",
+            Action::Confirm => "Confirm",
+            Action::Pause => "Pause",
+            Action::QuitToMenu => "Quit to menu",
+            Action::SteppingToggle => "Toggle stepping",
+            Action::SteppingStep => "Step system",
+            Action::SteppingContinue => "Step frame",
+            Action::SteppingDump => "Log stepping state",
+            Action::SteppingSelectPrevious => "Select previous system",
+            Action::SteppingSelectNext => "Select next system",
+            Action::SteppingBreakpoint => "Toggle breakpoint",
+            Action::SteppingSkip => "Toggle skipping system",
+            Action::SteppingAlwaysRun => "Toggle always running system",
+            Action::CollisionDebugToggle => "Toggle collision overlay",
+        }
+    }
+
+    fn default_bindings(self) -> Vec<Binding> {
+        match self {
+            Action::MoveLeft => vec![
+                Binding::Key(KeyCode::ArrowLeft),
+                Binding::Key(KeyCode::KeyA),
+                Binding::GamepadButton(GamepadButton::DPadLeft),
+                Binding::GamepadStick {
+                    axis: GamepadAxis::LeftStickX,
+                    positive: false,
+                },
+            ],
+            Action::MoveRight => vec![
+                Binding::Key(KeyCode::ArrowRight),
+                Binding::Key(KeyCode::KeyD),
+                Binding::GamepadButton(GamepadButton::DPadRight),
+                Binding::GamepadStick {
+                    axis: GamepadAxis::LeftStickX,
+                    positive: true,
+                },
+            ],
+            Action::Launch => vec![
+                Binding::Key(KeyCode::Space),
+                Binding::Mouse(MouseButton::Left),
+                Binding::GamepadButton(GamepadButton::South),
+                Binding::Touch,
+            ],
+            Action::Confirm => vec![
+                Binding::Key(KeyCode::Enter),
+                Binding::GamepadButton(GamepadButton::South),
+            ],
+            Action::Pause => vec![
+                Binding::Key(KeyCode::Escape),
+                Binding::GamepadButton(GamepadButton::Start),
+            ],
+            Action::QuitToMenu => vec![
+                Binding::Key(KeyCode::KeyQ),
+                Binding::GamepadButton(GamepadButton::Select),
+            ],
+            Action::SteppingToggle => vec![Binding::Key(KeyCode::Backquote)],
+            Action::SteppingStep => vec![Binding::Key(KeyCode::KeyS)],
+            Action::SteppingContinue => vec![Binding::Key(KeyCode::KeyC)],
+            Action::SteppingDump => vec![Binding::Key(KeyCode::Slash)],
+            Action::SteppingSelectPrevious => vec![Binding::Key(KeyCode::ArrowUp)],
+            Action::SteppingSelectNext => vec![Binding::Key(KeyCode::ArrowDown)],
+            Action::SteppingBreakpoint => vec![Binding::Key(KeyCode::KeyB)],
+            Action::SteppingSkip => vec![Binding::Key(KeyCode::KeyX)],
+            Action::SteppingAlwaysRun => vec![Binding::Key(KeyCode::KeyR)],
+            Action::CollisionDebugToggle => vec![Binding::Key(KeyCode::F3)],
+        }
+    }
+}
+
+/// An input that can trigger an [`Action`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// A button on any connected gamepad
+    GamepadButton(GamepadButton),
+    /// One direction of a stick on any connected gamepad
+    GamepadStick {
+        axis: GamepadAxis,
+        positive: bool,
+    },
+    /// A finger anywhere on a touch screen
+    Touch,
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => match key {
+                KeyCode::Backquote => write!(f, "`"),
+                KeyCode::Slash => write!(f, "/"),
+                KeyCode::Escape => write!(f, "Esc"),
+                KeyCode::ArrowLeft => write!(f, "Left"),
+                KeyCode::ArrowRight => write!(f, "Right"),
+                KeyCode::ArrowUp => write!(f, "Up"),
+                KeyCode::ArrowDown => write!(f, "Down"),
+                _ => {
+                    let name = format!("{key:?}");
+                    let name = name
+                        .strip_prefix("Key")
+                        .or_else(|| name.strip_prefix("Digit"))
+                        .unwrap_or(&name);
+                    write!(f, "{name}")
+                }
+            },
+            Binding::Mouse(button) => write!(f, "Mouse {button:?}"),
+            Binding::GamepadButton(button) => write!(f, "Pad {button:?}"),
+            Binding::GamepadStick { axis, positive } => {
+                write!(f, "Pad {axis:?}{}", if *positive { "+" } else { "-" })
+            }
+            Binding::Touch => write!(f, "Touch"),
+        }
+    }
+}
+
+/// Which inputs trigger which [`Action`]s, kept on disk between sessions
+#[derive(Resource, Debug)]
+pub struct InputBindings {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+    /// Whether the mouse cursor and touches steer the paddle
+    pub pointer: bool,
+    /// Where the bindings are saved, or `None` if they must not be written to
+    path: Option<PathBuf>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        InputBindings {
+            bindings: Action::ALL
+                .into_iter()
+                .map(|action| (action, action.default_bindings()))
+                .collect(),
+            pointer: true,
+            path: None,
+        }
+    }
+}
+
+impl InputBindings {
+    /// The inputs bound to `action`
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The inputs bound to `action`, for showing to the player
+    pub fn describe(&self, action: Action) -> String {
+        let bindings = self.get(action);
+        if bindings.is_empty() {
+            return "unbound".to_string();
+        }
+        bindings
+            .iter()
+            .map(Binding::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The other actions bound to any of the inputs that trigger `action`
+    fn shared_with(&self, action: Action) -> Vec<Action> {
+        let bindings = self.get(action);
+        Action::ALL
+            .into_iter()
+            .filter(|&other| {
+                other != action
+                    && self
+                        .get(other)
+                        .iter()
+                        .any(|binding| bindings.contains(binding))
+            })
+            .collect()
+    }
+
+    /// Add `binding` to the inputs that trigger `action`
+    fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    fn clear(&mut self, action: Action) {
+        self.bindings.insert(action, Vec::new());
+    }
+
+    /// Go back to the default bindings, still saving them to the same place
+    fn reset(&mut self) {
+        *self = InputBindings {
+            path: self.path.take(),
+            ..default()
+        };
+    }
+
+    fn load() -> InputBindings {
+        let Some(path) = bindings_path() else {
+            warn!("no config directory found, controls won't be saved");
+            return InputBindings::default();
+        };
+
+        match read_bindings(&path) {
+            Ok(Some(file)) => {
+                let mut bindings = InputBindings {
+                    bindings: file.bindings,
+                    pointer: file.pointer,
+                    path: Some(path),
+                };
+                // Actions added since the file was saved get their default bindings
+                for action in Action::ALL {
+                    bindings
+                        .bindings
+                        .entry(action)
+                        .or_insert_with(|| action.default_bindings());
+                }
+                bindings
+            }
+            Ok(None) => InputBindings {
+                path: Some(path),
+                ..default()
+            },
+            // Leave a file we can't make sense of alone rather than overwrite someone's controls
+            Err(error) => {
+                error!("{error}; controls won't be saved this session");
+                InputBindings::default()
+            }
+        }
+    }
+
+    fn save(&self) -> Result<(), BindingsError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = BindingsFile {
+            version: FILE_VERSION,
+            pointer: self.pointer,
+            bindings: self.bindings.clone(),
+        };
+        let contents = ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())?;
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let temporary = path.with_extension("ron.tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+/// Possible errors while reading or writing the bindings file
+#[derive(Debug, Error)]
+enum BindingsError {
+    #[error("could not access the bindings file: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not parse the bindings file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not write the bindings file: {0}")]
+    Write(#[from] ron::Error),
+    #[error("the bindings file has version {0}, but only version {FILE_VERSION} is known")]
+    UnknownVersion(u32),
+}
+
+/// Just the version of a bindings file, read before the rest of it
+#[derive(Deserialize)]
+struct FileVersion {
+    version: u32,
+}
+
+/// On-disk layout of the current [`FILE_VERSION`]
+#[derive(Serialize, Deserialize)]
+struct BindingsFile {
+    version: u32,
+    pointer: bool,
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+fn bindings_path() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join(env!("CARGO_PKG_NAME"))
+            .join(FILE_NAME),
+    )
+}
+
+/// Read the bindings file, or `None` if there isn't one yet
+fn read_bindings(path: &Path) -> Result<Option<BindingsFile>, BindingsError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    match ron::from_str::<FileVersion>(&contents)?.version {
+        FILE_VERSION => Ok(Some(ron::from_str(&contents)?)),
+        version => Err(BindingsError::UnknownVersion(version)),
+    }
+}
+
+/// The state of every [`Action`] this frame
+#[derive(Resource, Debug, Default)]
+pub struct Actions {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    /// Which way the paddle should move, from -1 (full speed left) to 1 (full speed right)
+    movement: f32,
+    /// Where the mouse cursor or a touch last put the paddle, until it is moved another way
+    pointer: Option<f32>,
+    /// Whether [`Action::Launch`] was pressed since the last fixed step, see [`read_actions`]
+    launch: bool,
+}
+
+impl Actions {
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    /// Press `action` for this frame, for when nobody is at the controls
+    pub fn press(&mut self, action: Action) {
+        if self.pressed.insert(action) {
+            self.just_pressed.insert(action);
+        }
+    }
+}
+
+/// A way around a menu, from keys and gamepad buttons that can't be rebound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+}
+
+/// The raw input devices that [`Binding`]s refer to
+#[derive(SystemParam)]
+pub struct RawInput<'w, 's> {
+    keyboard: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    touches: Res<'w, Touches>,
+    gamepads: Query<'w, 's, &'static Gamepad>,
+}
+
+impl RawInput<'_, '_> {
+    /// Which way around a menu has just been asked for, if any.
+    ///
+    /// Menus answer to fixed keys and the gamepad's d-pad rather than to
+    /// [`Action`]s, so that no choice of bindings can lock the player out of them.
+    pub fn menu_input(&self) -> Option<MenuInput> {
+        let pad = |button| {
+            self.gamepads
+                .iter()
+                .any(|gamepad| gamepad.just_pressed(button))
+        };
+        if self.keyboard.just_pressed(KeyCode::ArrowUp) || pad(GamepadButton::DPadUp) {
+            Some(MenuInput::Up)
+        } else if self.keyboard.just_pressed(KeyCode::ArrowDown) || pad(GamepadButton::DPadDown) {
+            Some(MenuInput::Down)
+        } else if self.keyboard.just_pressed(KeyCode::ArrowLeft) || pad(GamepadButton::DPadLeft) {
+            Some(MenuInput::Left)
+        } else if self.keyboard.just_pressed(KeyCode::ArrowRight) || pad(GamepadButton::DPadRight) {
+            Some(MenuInput::Right)
+        } else if self.keyboard.just_pressed(KeyCode::Enter) || pad(GamepadButton::South) {
+            Some(MenuInput::Select)
+        } else if self.keyboard.just_pressed(KeyCode::Escape) || pad(GamepadButton::East) {
+            Some(MenuInput::Back)
+        } else {
+            None
+        }
+    }
+
+    /// How strongly `binding` is held, from 0 to 1
+    fn strength(&self, binding: Binding) -> f32 {
+        let held = |pressed: bool| if pressed { 1.0 } else { 0.0 };
+        match binding {
+            Binding::Key(key) => held(self.keyboard.pressed(key)),
+            Binding::Mouse(button) => held(self.mouse.pressed(button)),
+            Binding::GamepadButton(button) => {
+                held(self.gamepads.iter().any(|gamepad| gamepad.pressed(button)))
+            }
+            Binding::GamepadStick { axis, positive } => self
+                .gamepads
+                .iter()
+                .filter_map(|gamepad| gamepad.get(axis))
+                .map(|value| {
+                    let value = if positive { value } else { -value };
+                    ((value - STICK_DEADZONE) / (1.0 - STICK_DEADZONE)).clamp(0.0, 1.0)
+                })
+                .fold(0.0, f32::max),
+            Binding::Touch => held(self.touches.iter().next().is_some()),
+        }
+    }
+
+    /// Some input that has just been pressed, to bind to an action
+    fn just_pressed(&self) -> Option<Binding> {
+        let key = self
+            .keyboard
+            .get_just_pressed()
+            .next()
+            .copied()
+            .map(Binding::Key);
+        let mouse = || {
+            self.mouse
+                .get_just_pressed()
+                .next()
+                .copied()
+                .map(Binding::Mouse)
+        };
+        let touch = || self.touches.any_just_pressed().then_some(Binding::Touch);
+        let button = || {
+            self.gamepads
+                .iter()
+                .find_map(|gamepad| gamepad.get_just_pressed().next().copied())
+                .map(Binding::GamepadButton)
+        };
+        let stick = || {
+            self.gamepads.iter().find_map(|gamepad| {
+                [
+                    GamepadAxis::LeftStickX,
+                    GamepadAxis::LeftStickY,
+                    GamepadAxis::RightStickX,
+                    GamepadAxis::RightStickY,
+                ]
+                .into_iter()
+                .find_map(|axis| {
+                    let value = gamepad.get(axis)?;
+                    (value.abs() > STICK_PRESS_THRESHOLD).then_some(Binding::GamepadStick {
+                        axis,
+                        positive: value > 0.0,
+                    })
+                })
+            })
+        };
+        key.or_else(mouse)
+            .or_else(touch)
+            .or_else(button)
+            .or_else(stick)
+    }
+}
+
+/// Work out the [`Actions`] from this frame's raw input.
+///
+/// While stepping, the left mouse button is busy with the stepping panel, so
+/// [`Action::Launch`] is ignored until stepping is disabled again.
+pub fn update_actions(
+    mut actions: ResMut<Actions>,
+    bindings: Res<InputBindings>,
+    input: RawInput,
+    stepping: Option<Res<Stepping>>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut last_cursor: Local<Option<Vec2>>,
+) {
+    let strength = |action| {
+        bindings
+            .get(action)
+            .iter()
+            .map(|&binding| input.strength(binding))
+            .fold(0.0, f32::max)
+    };
+
+    let stepping = stepping.is_some_and(|stepping| stepping.is_enabled());
+    let previous = std::mem::take(&mut actions.pressed);
+    actions.pressed = Action::ALL
+        .into_iter()
+        .filter(|&action| !(stepping && action == Action::Launch))
+        .filter(|&action| strength(action) >= STICK_PRESS_THRESHOLD)
+        .collect();
+    actions.just_pressed = actions.pressed.difference(&previous).copied().collect();
+    if actions.just_pressed(Action::Launch) {
+        actions.launch = true;
+    }
+    actions.movement = strength(Action::MoveRight) - strength(Action::MoveLeft);
+
+    if !bindings.pointer {
+        actions.pointer = None;
+        return;
+    }
+    if actions.movement != 0.0 {
+        // Moving the paddle any other way takes it away from the pointer
+        actions.pointer = None;
+    }
+    // Only a cursor that moves takes control, so a mouse left lying around doesn't fight the keys
+    let cursor = windows.iter().find_map(Window::cursor_position);
+    let moved_cursor = cursor.filter(|&cursor| *last_cursor != Some(cursor));
+    *last_cursor = cursor;
+    let touch = input.touches.iter().next().map(Touch::position);
+    let Some(position) = touch.or(moved_cursor) else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = cameras.get_single() else {
+        return;
+    };
+    if let Ok(world_position) = camera.viewport_to_world_2d(camera_transform, position) {
+        actions.pointer = Some(world_position.x);
+    }
+}
+
+/// Systems that decide on the [`PaddleCommand`] for the current fixed step.
+/// Anything reading the command should run after this set.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PaddleInput;
+
+/// What the paddle should do during one fixed step
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PaddleCommand {
+    /// Which way to move, from -1 (full speed left) to 1 (full speed right)
+    pub direction: f32,
+    /// Whether to send off any balls stuck to the paddle
+    pub launch: bool,
+    /// Where to move the paddle to at full speed instead, as the mouse and touches do
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<f32>,
+}
+
+/// Turn the [`Actions`] into this step's [`PaddleCommand`].
+///
+/// A press of [`Action::Launch`] is kept until a fixed step takes it, so that
+/// frames without a fixed step don't lose it, and only that step launches: a
+/// held button doesn't send off a ball the sticky paddle just caught.
+pub fn read_actions(mut actions: ResMut<Actions>, mut command: ResMut<PaddleCommand>) {
+    *command = PaddleCommand {
+        direction: actions.movement,
+        launch: std::mem::take(&mut actions.launch),
+        target: actions.pointer,
+    };
+}
+
+/// Forget a launch pressed before play started or resumed, such as the
+/// button press confirming the previous screen
+fn forget_launch(mut actions: ResMut<Actions>) {
+    actions.launch = false;
+}
+
+/// Where the controls screen is at
+#[derive(Resource, Default)]
+struct ControlsMenu {
+    /// Row of the selected action, with the pointer setting last
+    selected: usize,
+    /// Whether the next input pressed gets bound to the selected action
+    rebinding: bool,
+}
+
+/// Holds the rows of the controls screen
+#[derive(Component)]
+struct ControlsList;
+
+/// Returns a system that spawns the controls screen, despawned again when
+/// `state` is exited
+fn spawn_controls_screen<S: States + Copy>(state: S) -> impl Fn(Commands) {
+    move |mut commands: Commands| {
+        commands.insert_resource(ControlsMenu::default());
+        commands.spawn(screen_root(state)).with_children(|p| {
+            p.spawn(screen_title("Controls"));
+            p.spawn((
+                Node {
+                    display: Display::Grid,
+                    grid_template_columns: RepeatedGridTrack::auto(2),
+                    column_gap: Val::Px(30.0),
+                    row_gap: Val::Px(4.0),
+                    ..default()
+                },
+                ControlsList,
+            ));
+            p.spawn(screen_text(
+                "Up/Down: choose, Enter: add a binding, Backspace: clear, R: reset all, Esc: back",
+            ));
+        });
+    }
+}
+
+/// Move around the controls screen and change the bindings, see [`RawInput::menu_input`]
+fn navigate_controls(
+    input: RawInput,
+    mut menu: ResMut<ControlsMenu>,
+    mut bindings: ResMut<InputBindings>,
+    pause_menu: Option<Res<State<PauseMenu>>>,
+    mut next_pause_menu: ResMut<NextState<PauseMenu>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let keyboard = &input.keyboard;
+    if menu.rebinding {
+        if keyboard.just_pressed(KeyCode::Escape) {
+            menu.rebinding = false;
+        } else if let Some(binding) = input.just_pressed() {
+            bindings.bind(Action::ALL[menu.selected], binding);
+            if let Err(error) = bindings.save() {
+                error!("{error}");
+            }
+            menu.rebinding = false;
+        }
+        return;
+    }
+
+    let rows = Action::ALL.len() + 1;
+    let action = Action::ALL.get(menu.selected).copied();
+    match input.menu_input() {
+        Some(MenuInput::Up) => menu.selected = (menu.selected + rows - 1) % rows,
+        Some(MenuInput::Down) => menu.selected = (menu.selected + 1) % rows,
+        Some(MenuInput::Select) => match action {
+            Some(_) => menu.rebinding = true,
+            None => {
+                bindings.pointer = !bindings.pointer;
+                if let Err(error) = bindings.save() {
+                    error!("{error}");
+                }
+            }
+        },
+        // Back to the settings screen it was opened from
+        Some(MenuInput::Back) if pause_menu.is_some() => next_pause_menu.set(PauseMenu::Settings),
+        Some(MenuInput::Back) => next_state.set(GameState::Settings),
+        None if keyboard.any_just_pressed([KeyCode::Backspace, KeyCode::Delete]) => {
+            if let Some(action) = action {
+                bindings.clear(action);
+                if let Err(error) = bindings.save() {
+                    error!("{error}");
+                }
+            }
+        }
+        None if keyboard.just_pressed(KeyCode::KeyR) => {
+            bindings.reset();
+            if let Err(error) = bindings.save() {
+                error!("{error}");
+            }
+        }
+        Some(MenuInput::Left | MenuInput::Right) | None => (),
+    }
+}
+
+/// Lay out the bindings of every action, highlighting the selected one and
+/// flagging the inputs that trigger more than one action
+fn update_controls_screen(
+    mut commands: Commands,
+    menu: Res<ControlsMenu>,
+    bindings: Res<InputBindings>,
+    list: Single<Entity, With<ControlsList>>,
+) {
+    // The label, the bindings and whether they are shared with another action
+    let mut rows: Vec<(&str, String, bool)> = Action::ALL
+        .into_iter()
+        .map(|action| {
+            let shared_with = bindings.shared_with(action);
+            if shared_with.is_empty() {
+                return (action.label(), bindings.describe(action), false);
+            }
+            let labels: Vec<&str> = shared_with.into_iter().map(Action::label).collect();
+            let description = format!("{} (also {})", bindings.describe(action), labels.join(", "));
+            (action.label(), description, true)
+        })
+        .collect();
+    rows.push((
+        "Mouse and touch steer the paddle",
+        if bindings.pointer { "on" } else { "off" }.to_string(),
+        false,
+    ));
+    if menu.rebinding {
+        rows[menu.selected].1 = "press a key or button, or Esc to cancel".to_string();
+    }
+
+    commands
+        .entity(*list)
+        .despawn_descendants()
+        .with_children(|p| {
+            for (row, (label, bindings, shared)) in rows.into_iter().enumerate() {
+                let color = if row == menu.selected {
+                    CONTROLS_HIGHLIGHT_COLOR
+                } else {
+                    CONTROLS_TEXT_COLOR
+                };
+                let bindings_color = if shared && row != menu.selected {
+                    CONTROLS_SHARED_COLOR
+                } else {
+                    color
+                };
+                for (cell, color) in [(label.to_string(), color), (bindings, bindings_color)] {
+                    p.spawn((
+                        Text::new(cell),
+                        TextFont {
+                            font_size: CONTROLS_FONT_SIZE,
+                            ..default()
+                        },
+                        TextColor(color),
+                    ));
+                }
+            }
+        });
+}
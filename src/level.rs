@@ -0,0 +1,568 @@
+//! Data-driven levels.
+//!
+//! Levels are RON files in `assets/levels/` describing the brick layout as a
+//! grid of characters plus a legend mapping each character to a kind of brick.
+//! The order in which levels are played comes from a campaign file in the same
+//! directory, so new levels can be added without recompiling the game.
+//!
+//! ```ron
+//! (
+//!     name: "Example",
+//!     legend: {
+//!         'R': (kind: Reinforced, color: (1.0, 0.4, 0.4), hits: 2),
+//!         'B': (color: (0.5, 0.5, 1.0)),
+//!         'X': (kind: Unbreakable, color: (0.3, 0.3, 0.3)),
+//!     },
+//!     rows: [
+//!         "RRRR",
+//!         "B..B",
+//!         ".XX.",
+//!     ],
+//! )
+//! ```
+//!
+//! Every row is centered horizontally and the first row sits just below the
+//! ceiling. `.` and spaces leave a gap in the grid. An optional `speed_scale`
+//! makes the balls faster (or slower) than usual on that level, see the
+//! `difficulty` module, and an optional `music` names a track to loop while it
+//! is played, relative to the `assets` directory, see the `sound` module.
+//!
+//! Whatever has to be set up again when a level starts over, whether the
+//! player moved on to it or restarted it, goes in the [`StartLevel`] schedule.
+//! The first level of a game is set up on entering [`InGame`] instead.
+
+use std::{collections::HashMap, path::Path};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext, RecursiveDependencyLoadState},
+    ecs::{schedule::ScheduleLabel, system::SystemParam},
+    prelude::*,
+};
+use ron::extensions::Extensions;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    config::GameConfig,
+    input::{Action, Actions},
+    replay::Playback,
+    state::{GameState, InGame},
+    Ball, BallAssets, Brick, BrickColor, BrickKind, Collider, Health, Lives, Paddle, Score,
+};
+
+/// Campaign loaded at startup, relative to the `assets` directory
+const CAMPAIGN_PATH: &str = "levels/breakout.campaign.ron";
+
+/// Plugin loading the campaign and spawning the bricks of the current level
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Level>()
+            .init_asset::<Campaign>()
+            .init_asset_loader::<LevelLoader>()
+            .init_asset_loader::<CampaignLoader>()
+            .init_resource::<CurrentLevel>()
+            .init_resource::<FirstLevel>()
+            .init_resource::<LevelSpeedScale>()
+            .init_resource::<LevelCheckpoint>()
+            .init_schedule(StartLevel)
+            .add_observer(restart_level)
+            .add_systems(Startup, load_campaign)
+            .add_systems(Update, finish_loading.run_if(in_state(GameState::Loading)))
+            .add_systems(
+                Update,
+                advance_level.run_if(in_state(GameState::LevelComplete)),
+            )
+            .add_systems(
+                OnEnter(InGame),
+                (
+                    (start_campaign, spawn_bricks).chain(),
+                    save_checkpoint.after(crate::spawn_arena),
+                ),
+            )
+            .add_systems(
+                OnExit(InGame),
+                reset_first_level.run_if(not(resource_exists::<Playback>)),
+            )
+            .add_systems(
+                OnTransition {
+                    exited: GameState::LevelComplete,
+                    entered: GameState::Playing,
+                },
+                run_start_level,
+            )
+            .add_systems(
+                StartLevel,
+                (despawn_bricks, spawn_bricks, reset_balls, save_checkpoint).chain(),
+            );
+    }
+}
+
+/// A single level, loaded from a `.level.ron` file
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct Level {
+    /// Name shown to the player when the level starts
+    pub name: String,
+    /// Kind of brick placed for each character used in `rows`
+    legend: HashMap<char, BrickDef>,
+    /// Brick grid, top row first
+    rows: Vec<String>,
+    /// Multiplies every ball speed on this level
+    #[serde(default = "default_speed_scale")]
+    speed_scale: f32,
+    /// Asset path of the music looped while the level is played, if any
+    #[serde(default)]
+    pub music: Option<String>,
+}
+
+fn default_speed_scale() -> f32 {
+    1.0
+}
+
+/// How much faster than usual the balls of the current level go
+#[derive(Resource, Debug, Deref, DerefMut)]
+pub struct LevelSpeedScale(f32);
+
+impl Default for LevelSpeedScale {
+    fn default() -> Self {
+        LevelSpeedScale(default_speed_scale())
+    }
+}
+
+/// Schedule run whenever the [`CurrentLevel`] starts over with a fresh set of
+/// bricks, either because the previous level was cleared or because the player
+/// asked to [`RestartLevel`]
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StartLevel;
+
+/// Event starting the current level over, with the score and lives the player
+/// had when it started
+#[derive(Event, Debug)]
+pub struct RestartLevel;
+
+/// Score and lives at the start of the current level, for [`RestartLevel`]
+#[derive(Resource, Debug, Default)]
+struct LevelCheckpoint {
+    score: usize,
+    lives: usize,
+}
+
+/// Row of the level's grid a brick was placed in, counting from the top
+#[derive(Component, Debug, Clone, Copy, Deref)]
+pub struct BrickRow(pub usize);
+
+/// How a character in a level's grid is turned into a brick
+#[derive(Debug, Clone, Deserialize)]
+struct BrickDef {
+    /// Behavior of the brick, [`BrickKind::Standard`] if left out
+    #[serde(default)]
+    kind: BrickKind,
+    /// sRGB color of the brick
+    color: (f32, f32, f32),
+    /// Number of times the ball has to hit the brick to destroy it,
+    /// overriding the default for its kind
+    #[serde(default)]
+    hits: Option<u32>,
+}
+
+impl BrickDef {
+    /// Hit points of the brick, or `None` if it can't be broken
+    fn hits(&self) -> Option<u32> {
+        self.hits.or(self.kind.default_hits())
+    }
+}
+
+/// Characters that leave an empty cell in a level's grid
+const EMPTY_CELLS: [char; 2] = ['.', ' '];
+
+impl Level {
+    /// Iterate over every brick of the level along with the center of its cell and its row
+    fn bricks<'a>(
+        &'a self,
+        config: &GameConfig,
+    ) -> impl Iterator<Item = (Vec2, usize, &'a BrickDef)> {
+        let n_columns = self
+            .rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let n_vertical_gaps = n_columns.saturating_sub(1);
+
+        let arena = &config.arena;
+        let brick_size = config.bricks.size;
+        let gap = config.bricks.gap;
+
+        let center_of_bricks = (arena.left + arena.right) / 2.0;
+        let left_edge_of_bricks = center_of_bricks
+            // Space taken up by the bricks
+            - (n_columns as f32 / 2.0 * brick_size.x)
+            // Space taken up by the gaps
+            - n_vertical_gaps as f32 / 2.0 * gap;
+        let top_edge_of_bricks =
+            arena.top - arena.wall_thickness / 2. - config.bricks.gap_to_ceiling;
+
+        // In Bevy, the `translation` of an entity describes the center point,
+        // not its top-left corner
+        let offset_x = left_edge_of_bricks + brick_size.x / 2.;
+        let offset_y = top_edge_of_bricks - brick_size.y / 2.;
+
+        self.rows.iter().enumerate().flat_map(move |(row, cells)| {
+            cells.chars().enumerate().filter_map(move |(column, cell)| {
+                let def = self.legend.get(&cell)?;
+                let position = Vec2::new(
+                    offset_x + column as f32 * (brick_size.x + gap),
+                    offset_y - row as f32 * (brick_size.y + gap),
+                );
+                Some((position, row, def))
+            })
+        })
+    }
+
+    /// Warn about layouts that won't leave the player enough room
+    fn check_fits_arena(&self, config: &GameConfig) {
+        let name = &self.name;
+        let arena = &config.arena;
+        let bricks = &config.bricks;
+
+        let n_columns = self.rows.iter().map(|row| row.chars().count()).max();
+        let n_columns = n_columns.unwrap_or(0) as f32;
+        let n_rows = self.rows.len() as f32;
+
+        let width = n_columns * bricks.size.x + (n_columns - 1.).max(0.) * bricks.gap;
+        let available_width = (arena.right - arena.left) - 2. * bricks.gap_to_sides;
+        if width > available_width {
+            warn!("level {name} is {width} wide, but only {available_width} fits in the arena");
+        }
+
+        let height = n_rows * bricks.size.y + (n_rows - 1.).max(0.) * bricks.gap;
+        let available_height = arena.top
+            - arena.wall_thickness / 2.
+            - bricks.gap_to_ceiling
+            - (config.paddle_y() + bricks.gap_to_paddle);
+        if height > available_height {
+            warn!(
+                "level {name} is {height} tall, but only {available_height} fits above the paddle"
+            );
+        }
+    }
+}
+
+/// Possible errors produced by [`LevelLoader`] and [`CampaignLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum LevelLoaderError {
+    /// An [IO](std::io) Error
+    #[error("could not read level file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON](ron) Error
+    #[error("could not parse level file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    /// The grid uses a character the legend doesn't define
+    #[error("row {row} uses '{cell}', which is missing from the legend")]
+    UnknownCell { row: usize, cell: char },
+    /// A legend entry gives hit points that don't make sense for its kind
+    #[error("brick '{cell}' of kind {kind:?} can't have {hits} hit points")]
+    InvalidHits {
+        cell: char,
+        kind: BrickKind,
+        hits: u32,
+    },
+    /// The grid has nothing to break, so the level would be won as soon as it starts
+    #[error("the level has no breakable bricks")]
+    NoBreakableBricks,
+}
+
+#[derive(Default)]
+struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Level, LevelLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        // Lets the legend write `hits: 2` rather than `hits: Some(2)`
+        let options = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+        let level: Level = options.from_bytes(&bytes)?;
+
+        for (&cell, def) in &level.legend {
+            match (def.kind, def.hits) {
+                (BrickKind::Unbreakable, Some(hits)) | (_, Some(hits @ 0)) => {
+                    return Err(LevelLoaderError::InvalidHits {
+                        cell,
+                        kind: def.kind,
+                        hits,
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        for (row, cells) in level.rows.iter().enumerate() {
+            if let Some(cell) = cells
+                .chars()
+                .find(|cell| !EMPTY_CELLS.contains(cell) && !level.legend.contains_key(cell))
+            {
+                return Err(LevelLoaderError::UnknownCell { row, cell });
+            }
+        }
+
+        let breakable = |cell| {
+            level
+                .legend
+                .get(&cell)
+                .is_some_and(|def| def.kind != BrickKind::Unbreakable)
+        };
+        if !level.rows.iter().flat_map(|row| row.chars()).any(breakable) {
+            return Err(LevelLoaderError::NoBreakableBricks);
+        }
+
+        Ok(level)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+/// The ordered list of levels making up a game, loaded from a `.campaign.ron` file
+#[derive(Asset, TypePath, Debug)]
+pub struct Campaign {
+    #[dependency]
+    levels: Vec<Handle<Level>>,
+}
+
+/// On-disk representation of a [`Campaign`].
+/// Level paths are relative to the campaign file.
+#[derive(Deserialize)]
+struct CampaignFile {
+    levels: Vec<String>,
+}
+
+#[derive(Default)]
+struct CampaignLoader;
+
+impl AssetLoader for CampaignLoader {
+    type Asset = Campaign;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Campaign, LevelLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: CampaignFile = ron::de::from_bytes(&bytes)?;
+
+        let directory = load_context
+            .path()
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let levels = file
+            .levels
+            .iter()
+            .map(|level| load_context.load(directory.join(level)))
+            .collect();
+
+        Ok(Campaign { levels })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["campaign.ron"]
+    }
+}
+
+#[derive(Resource, Deref)]
+struct CampaignHandle(Handle<Campaign>);
+
+/// Index into the campaign of the level being played
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct CurrentLevel(usize);
+
+/// Index into the campaign of the level new games start at, picked on the
+/// level select screen or by a replay. Back to the first level once the game
+/// is over, unless a replay is being played.
+#[derive(Resource, Debug, Default, Clone, Copy, Deref, DerefMut)]
+pub struct FirstLevel(pub usize);
+
+/// The levels of the loaded campaign, in the order they are played
+#[derive(SystemParam)]
+pub struct CampaignLevels<'w> {
+    campaign: Res<'w, CampaignHandle>,
+    campaigns: Res<'w, Assets<Campaign>>,
+    levels: Res<'w, Assets<Level>>,
+}
+
+impl CampaignLevels<'_> {
+    /// Number of levels in the campaign, or 0 if it isn't loaded
+    pub fn count(&self) -> usize {
+        self.campaigns
+            .get(&**self.campaign)
+            .map_or(0, |campaign| campaign.levels.len())
+    }
+
+    /// The level at `index`, if it is loaded
+    pub fn get(&self, index: usize) -> Option<&Level> {
+        let campaign = self.campaigns.get(&**self.campaign)?;
+        self.levels.get(campaign.levels.get(index)?)
+    }
+}
+
+fn load_campaign(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(CampaignHandle(asset_server.load(CAMPAIGN_PATH)));
+}
+
+/// Leave the loading screen once the campaign and all of its levels are ready
+fn finish_loading(
+    asset_server: Res<AssetServer>,
+    campaign: Res<CampaignHandle>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut reported: Local<bool>,
+) {
+    match asset_server.get_recursive_dependency_load_state(&**campaign) {
+        Some(RecursiveDependencyLoadState::Loaded) => next_state.set(GameState::MainMenu),
+        Some(RecursiveDependencyLoadState::Failed(error)) if !*reported => {
+            error!("could not load the campaign {CAMPAIGN_PATH}: {error}");
+            *reported = true;
+        }
+        _ => (),
+    }
+}
+
+/// Every new game starts at the [`FirstLevel`]
+fn start_campaign(mut current_level: ResMut<CurrentLevel>, first_level: Res<FirstLevel>) {
+    **current_level = **first_level;
+}
+
+/// Start the next game from the beginning of the campaign again
+fn reset_first_level(mut first_level: ResMut<FirstLevel>) {
+    **first_level = 0;
+}
+
+/// Move on to the next level when the player is ready, or back to the menu
+/// once the whole campaign has been cleared
+fn advance_level(
+    actions: Res<Actions>,
+    levels: CampaignLevels,
+    mut current_level: ResMut<CurrentLevel>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !actions.just_pressed(Action::Confirm) {
+        return;
+    }
+
+    if **current_level + 1 < levels.count() {
+        **current_level += 1;
+        next_state.set(GameState::Playing);
+    } else {
+        info!("campaign complete");
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+fn run_start_level(world: &mut World) {
+    world.run_schedule(StartLevel);
+}
+
+fn save_checkpoint(mut checkpoint: ResMut<LevelCheckpoint>, score: Res<Score>, lives: Res<Lives>) {
+    *checkpoint = LevelCheckpoint {
+        score: **score,
+        lives: **lives,
+    };
+}
+
+fn restart_level(
+    _: Trigger<RestartLevel>,
+    mut commands: Commands,
+    checkpoint: Res<LevelCheckpoint>,
+    current_level: Res<CurrentLevel>,
+    mut score: ResMut<Score>,
+    mut lives: ResMut<Lives>,
+) {
+    info!("restarting level {}", **current_level + 1);
+    **score = checkpoint.score;
+    **lives = checkpoint.lives;
+    commands.queue(run_start_level);
+}
+
+fn despawn_bricks(mut commands: Commands, bricks: Query<Entity, With<Brick>>) {
+    for brick in &bricks {
+        commands.entity(brick).despawn();
+    }
+}
+
+/// Spawn the bricks of the [`CurrentLevel`], or go back to the menu if it
+/// isn't loaded, rather than leave an empty arena that counts as cleared
+fn spawn_bricks(
+    mut commands: Commands,
+    levels: CampaignLevels,
+    current_level: Res<CurrentLevel>,
+    mut level_speed_scale: ResMut<LevelSpeedScale>,
+    config: Res<GameConfig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(level) = levels.get(**current_level) else {
+        error!(
+            "level {} is not loaded, going back to the menu",
+            **current_level + 1
+        );
+        next_state.set(GameState::MainMenu);
+        return;
+    };
+    info!("starting level {}: {}", **current_level + 1, level.name);
+    // The arena can change while the game runs, so this is checked here rather than when loading
+    level.check_fits_arena(&config);
+
+    **level_speed_scale = level.speed_scale;
+
+    for (position, row, def) in level.bricks(&config) {
+        let (r, g, b) = def.color;
+        let color = Color::srgb(r, g, b);
+        let mut brick = commands.spawn((
+            Sprite { color, ..default() },
+            Transform {
+                translation: position.extend(0.0),
+                scale: config.bricks.size.extend(1.0),
+                ..default()
+            },
+            Brick,
+            BrickRow(row),
+            def.kind,
+            BrickColor(color),
+            Collider,
+            StateScoped(InGame),
+        ));
+        // Unbreakable bricks have no health to lose
+        if let Some(hits) = def.hits() {
+            brick.insert(Health::new(hits));
+        }
+    }
+}
+
+/// Put the paddle back in the middle, and replace however many balls are left with a fresh one
+fn reset_balls(
+    mut commands: Commands,
+    balls: Query<Entity, With<Ball>>,
+    mut paddle_transform: Single<&mut Transform, With<Paddle>>,
+    ball_assets: Res<BallAssets>,
+    config: Res<GameConfig>,
+) {
+    for ball in &balls {
+        commands.entity(ball).despawn();
+    }
+    paddle_transform.translation.x = (config.arena.left + config.arena.right) / 2.0;
+    commands.spawn(ball_assets.served_ball(paddle_transform.translation.x, &config));
+}
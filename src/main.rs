@@ -1,68 +1,258 @@
 //! A simplified implementation of the classic game "Breakout".
 //!
 //! Demonstrates Bevy's stepping capabilities if compiled with the `bevy_debug_stepping` feature.
+//! Run with `--headless` to simulate the game without a window, see the `headless` module.
+
+use std::{cmp::Ordering, path::PathBuf};
 
 use bevy::{
     math::bounding::{Aabb2d, BoundingCircle, BoundingVolume, IntersectsVolume},
     prelude::*,
 };
+use serde::Deserialize;
 
+mod collision_debug;
+mod config;
+mod difficulty;
+mod headless;
+mod highscore;
+mod input;
+mod level;
+mod menu;
+mod pause;
+mod powerup;
+mod replay;
+mod rng;
+mod scoring;
+mod settings;
+mod sound;
+mod state;
 mod stepping;
 
-// These constants are defined in `Transform` units.
-// Using the default 2D camera they correspond 1:1 with screen pixels.
-const PADDLE_SIZE: Vec2 = Vec2::new(120.0, 20.0);
-const GAP_BETWEEN_PADDLE_AND_FLOOR: f32 = 60.0;
-const PADDLE_SPEED: f32 = 500.0;
-// How close can the paddle get to the wall
-const PADDLE_PADDING: f32 = 10.0;
+use collision_debug::CollisionDebugPlugin;
+use config::{srgb, ArenaConfig, ConfigOverride, ConfigPlugin, GameConfig, DEFAULT_CONFIG_PATH};
+use difficulty::{BallSpeed, Difficulty, DifficultyPlugin};
+use highscore::HighScorePlugin;
+use input::{Action, InputBindings, PaddleCommand, PaddleInput, PaddleInputPlugin};
+use level::{BrickRow, FirstLevel, LevelPlugin};
+use menu::MenuPlugin;
+use pause::PausePlugin;
+use powerup::{PowerUpPlugin, Sticky};
+use replay::{Replay, ReplayPlugin};
+use rng::RngPlugin;
+use scoring::{Combo, ScoringPlugin};
+use settings::SettingsPlugin;
+use sound::SoundPlugin;
+use state::{simulating, GameState, GameStatePlugin, InGame};
 
 // We set the z-value of the ball to 1 so it renders on top in the case of overlapping sprites.
-const BALL_STARTING_POSITION: Vec3 = Vec3::new(0.0, -50.0, 1.0);
-const BALL_DIAMETER: f32 = 30.;
-const BALL_SPEED: f32 = 400.0;
-const INITIAL_BALL_DIRECTION: Vec2 = Vec2::new(0.5, -0.5);
-
-const WALL_THICKNESS: f32 = 10.0;
-// x coordinates
-const LEFT_WALL: f32 = -450.;
-const RIGHT_WALL: f32 = 450.;
-// y coordinates
-const BOTTOM_WALL: f32 = -300.;
-const TOP_WALL: f32 = 300.;
-
-const BRICK_SIZE: Vec2 = Vec2::new(100., 30.);
-// These values are exact
-const GAP_BETWEEN_PADDLE_AND_BRICKS: f32 = 270.0;
-const GAP_BETWEEN_BRICKS: f32 = 5.0;
-// These values are lower bounds, as the number of bricks is computed
-const GAP_BETWEEN_BRICKS_AND_CEILING: f32 = 20.0;
-const GAP_BETWEEN_BRICKS_AND_SIDES: f32 = 20.0;
+const BALL_Z: f32 = 1.0;
+// How many times the ball may bounce within a single fixed step before the rest of its
+// movement for that step is dropped
+const MAX_BOUNCES_PER_STEP: usize = 4;
 
+// Everything else that tunes the game's feel lives in `GameConfig`, see the `config` module
 const SCOREBOARD_FONT_SIZE: f32 = 33.0;
 const SCOREBOARD_TEXT_PADDING: Val = Val::Px(5.0);
+const SERVE_HINT_FONT_SIZE: f32 = 20.0;
+const SERVE_HINT_BOTTOM: Val = Val::Px(20.0);
 
-const BACKGROUND_COLOR: Color = Color::srgb(0.9, 0.9, 0.9);
-const PADDLE_COLOR: Color = Color::srgb(0.3, 0.3, 0.7);
-const BALL_COLOR: Color = Color::srgb(1.0, 0.5, 0.5);
-const BRICK_COLOR: Color = Color::srgb(0.5, 0.5, 1.0);
-const WALL_COLOR: Color = Color::srgb(0.8, 0.8, 0.8);
 const TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 1.0);
 const SCORE_COLOR: Color = Color::srgb(1.0, 0.5, 0.5);
 
-fn main() {
+// Options given on the command line
+#[derive(Default)]
+struct Args {
+    // `--seed <N>` makes the random parts of the game repeatable
+    seed: Option<u64>,
+    // `--config <path>` picks a different config file from the `assets` directory
+    config: Option<String>,
+    // `--set section.field=value` overrides a single config value, and can be repeated
+    overrides: Vec<ConfigOverride>,
+    // `--difficulty easy|normal|hard` picks how fast the balls go, see the `difficulty` module,
+    // instead of the difficulty picked in the settings
+    difficulty: Option<Difficulty>,
+    // `--headless` simulates the game without a window, for `--ticks <N>` fixed steps
+    headless: bool,
+    ticks: Option<u32>,
+    // `--record <file>` saves the latest game so that it can be replayed, see the `replay` module
+    record: Option<PathBuf>,
+    // `--replay <file>` plays a recorded game back
+    replay: Option<PathBuf>,
+    // `--step <pattern>` only steps the systems matching one of the patterns, and
+    // `--no-step <pattern>` never steps the ones matching, see `stepping::SystemFilter`.
+    // Both can be repeated.
+    step: Vec<String>,
+    no_step: Vec<String>,
+}
+
+impl Args {
+    // Logging isn't set up yet this early on, so problems are reported on stderr
+    fn parse() -> Args {
+        let mut parsed = Args::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    parsed.seed = args.next().and_then(|seed| seed.parse().ok());
+                    if parsed.seed.is_none() {
+                        eprintln!("--seed needs a number, picking a random seed instead");
+                    }
+                }
+                "--config" => match args.next() {
+                    Some(path) => parsed.config = Some(path),
+                    None => eprintln!("--config needs a path, using the default config file"),
+                },
+                "--set" => match args.next().map(|setting| setting.parse()) {
+                    Some(Ok(setting)) => parsed.overrides.push(setting),
+                    Some(Err(error)) => eprintln!("ignoring --set: {error}"),
+                    None => eprintln!("--set needs a value like `ball.speed=450`"),
+                },
+                "--difficulty" => match args.next().map(|name| name.parse()) {
+                    Some(Ok(difficulty)) => parsed.difficulty = Some(difficulty),
+                    Some(Err(error)) => eprintln!("ignoring --difficulty: {error}"),
+                    None => eprintln!("--difficulty needs one of easy, normal or hard"),
+                },
+                "--headless" => parsed.headless = true,
+                "--ticks" => {
+                    parsed.ticks = args.next().and_then(|ticks| ticks.parse().ok());
+                    if parsed.ticks.is_none() {
+                        eprintln!("--ticks needs a number, using the default");
+                    }
+                }
+                "--record" => match args.next() {
+                    Some(path) => parsed.record = Some(path.into()),
+                    None => eprintln!("--record needs a path, not recording"),
+                },
+                "--replay" => match args.next() {
+                    Some(path) => parsed.replay = Some(path.into()),
+                    None => eprintln!("--replay needs a path, playing normally"),
+                },
+                "--step" => match args.next() {
+                    Some(pattern) => parsed.step.push(pattern),
+                    None => eprintln!("--step needs a pattern like `mygame::*`"),
+                },
+                "--no-step" => match args.next() {
+                    Some(pattern) => parsed.no_step.push(pattern),
+                    None => eprintln!("--no-step needs a pattern like `mygame::input::*`"),
+                },
+                _ => eprintln!("ignoring unknown argument {arg}"),
+            }
+        }
+        parsed
+    }
+}
+
+fn main() -> AppExit {
+    let args = Args::parse();
+    let replay = match args.replay.as_deref().map(Replay::load).transpose() {
+        Ok(replay) => replay,
+        Err(error) => {
+            eprintln!("{error}");
+            return AppExit::error();
+        }
+    };
+    let difficulty_fixed = args.difficulty.is_some() || replay.is_some();
+    let breakout = BreakoutPlugin {
+        config_path: args
+            .config
+            .unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string()),
+        overrides: args.overrides,
+        seed: args.seed,
+        difficulty: args.difficulty.unwrap_or_default(),
+        record: args.record,
+        replay,
+    };
+
+    if args.headless {
+        return headless::run(breakout, args.ticks);
+    }
+
+    let mut stepping = stepping::SteppingPlugin::default()
+        .add_schedule(Update)
+        .add_schedule(FixedUpdate)
+        .at(Val::Percent(35.0), Val::Percent(5.0));
+    for pattern in args.step {
+        stepping = stepping.include(pattern);
+    }
+    for pattern in args.no_step {
+        stepping = stepping.exclude(pattern);
+    }
+
     App::new()
         .add_plugins(DefaultPlugins)
-        .add_plugins(
-            stepping::SteppingPlugin::default()
-                .add_schedule(Update)
-                .add_schedule(FixedUpdate)
-                .at(Val::Percent(35.0), Val::Percent(50.0)),
-        )
+        .add_plugins(breakout)
+        .add_plugins((
+            HighScorePlugin,
+            MenuPlugin,
+            PausePlugin,
+            SettingsPlugin { difficulty_fixed },
+            SoundPlugin,
+        ))
+        .add_plugins(CollisionDebugPlugin)
+        .add_plugins(stepping)
+        .add_systems(Startup, setup_camera)
+        .run()
+}
+
+/// The whole game apart from rendering, audio, the menus, the settings and the high-score table.
+///
+/// This only needs `MinimalPlugins` plus assets, states, input and text to run,
+/// which is how the `headless` module runs it.
+struct BreakoutPlugin {
+    /// Asset path of the config file
+    config_path: String,
+    /// Config values set on the command line
+    overrides: Vec<ConfigOverride>,
+    /// Seed for [`RngPlugin`], or `None` for a random one
+    seed: Option<u64>,
+    difficulty: Difficulty,
+    /// Where to save a recording of the latest game
+    record: Option<PathBuf>,
+    /// Recorded game to play back, whose seed and config win over the ones above
+    replay: Option<Replay>,
+}
+
+impl Plugin for BreakoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ConfigPlugin {
+            path: self.config_path.clone(),
+            overrides: self.overrides.clone(),
+            fixed: self.replay.as_ref().map(|replay| replay.config.clone()),
+        })
+        .add_plugins((
+            GameStatePlugin,
+            LevelPlugin,
+            PowerUpPlugin,
+            PaddleInputPlugin,
+            ScoringPlugin,
+        ))
+        .add_plugins(RngPlugin {
+            seed: self.replay.as_ref().map(|replay| replay.seed).or(self.seed),
+        })
+        .add_plugins(DifficultyPlugin {
+            difficulty: self
+                .replay
+                .as_ref()
+                .map_or(self.difficulty, |replay| replay.difficulty),
+        })
+        .add_plugins(ReplayPlugin {
+            record: self.record.clone(),
+            playback: self.replay.clone(),
+        })
+        .insert_resource(FirstLevel(
+            self.replay.as_ref().map_or(0, |replay| replay.first_level),
+        ))
+        .init_resource::<ClearColor>()
         .insert_resource(Score(0))
-        .insert_resource(ClearColor(BACKGROUND_COLOR))
+        .insert_resource(Lives(0))
         .add_event::<CollisionEvent>()
+        .add_event::<BallLost>()
+        .add_event::<LifeLost>()
+        .add_event::<BrickDestroyed>()
         .add_systems(Startup, setup)
+        // The arena is rebuilt every time a new game starts
+        .add_systems(OnEnter(InGame), spawn_arena)
         // Add our gameplay simulation systems to the fixed timestep schedule
         // which runs at 64 Hz by default
         .add_systems(
@@ -70,14 +260,31 @@ fn main() {
             (
                 apply_velocity,
                 move_paddle,
+                follow_paddle,
+                launch_stuck_balls,
                 check_for_collisions,
-                play_collision_sound,
+                break_bricks,
+                check_for_ball_lost,
+                check_for_level_cleared,
             )
                 // `chain`ing systems together runs them in order
-                .chain(),
+                .chain()
+                .after(PaddleInput)
+                // Only simulate while the ball is actually in play
+                .run_if(simulating),
+        )
+        .add_systems(
+            FixedUpdate,
+            log_collisions
+                .after(check_for_collisions)
+                .run_if(simulating),
         )
-        .add_systems(Update, update_scoreboard)
-        .run();
+        .add_systems(
+            Update,
+            (update_scoreboard, update_brick_damage, update_serve_hint).run_if(in_state(InGame)),
+        )
+        .add_systems(Update, apply_config.run_if(resource_changed::<GameConfig>));
+    }
 }
 
 #[derive(Component)]
@@ -86,20 +293,166 @@ struct Paddle;
 #[derive(Component)]
 struct Ball;
 
+/// A ball resting on the paddle, waiting to be launched.
+/// New balls are served like this, and a sticky paddle catches balls the same way.
+#[derive(Component)]
+struct StuckToPaddle {
+    /// Horizontal distance from the center of the paddle to the ball
+    offset: f32,
+}
+
 #[derive(Component, Deref, DerefMut)]
 struct Velocity(Vec2);
 
 #[derive(Component)]
 struct Collider;
 
-#[derive(Event, Default)]
-struct CollisionEvent;
+// Sent whenever a ball bounces off something, with everything systems reacting to it need to know
+#[derive(Event, Debug, Clone, Copy)]
+struct CollisionEvent {
+    /// The ball that bounced
+    ball: Entity,
+    /// What the ball bounced off
+    collider: Entity,
+    hit: Hit,
+    /// Where the ball touched the collider
+    point: Vec2,
+    /// The side of the collider that was hit
+    side: Collision,
+    /// Surface normal the ball was reflected off, which differs from the side's
+    /// when the ball clipped a corner
+    normal: Vec2,
+    /// The ball's velocity just before the bounce
+    velocity_before: Vec2,
+    /// The ball's velocity just after the bounce, zero if a sticky paddle caught it
+    velocity_after: Vec2,
+}
+
+/// What kind of thing a ball bounced off, and what the bounce did to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hit {
+    Wall(WallLocation),
+    Paddle,
+    Brick {
+        kind: BrickKind,
+        /// Row of the level's grid the brick was placed in, see [`BrickRow`]
+        row: usize,
+        /// Whether the hit took the brick's last hit point
+        destroyed: bool,
+    },
+}
+
+// Balls touching an entity with this component are lost instead of bouncing
+#[derive(Component)]
+struct KillZone;
+
+// Sent when a ball touches a kill zone
+#[derive(Event)]
+struct BallLost(Entity);
+
+// Sent when the last ball in play is lost and the player loses a life
+#[derive(Event)]
+struct LifeLost;
 
 #[derive(Component)]
 struct Brick;
 
-#[derive(Resource, Deref)]
-struct CollisionSound(Handle<AudioSource>);
+/// What kind of brick this is, which decides how it reacts to the ball
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum BrickKind {
+    /// Breaks on the first hit
+    #[default]
+    Standard,
+    /// Takes several hits to break, fading a little with each one
+    Reinforced,
+    /// Can't be broken and doesn't need to be cleared to finish the level
+    Unbreakable,
+}
+
+impl BrickKind {
+    /// Number of hits needed to break a brick of this kind, unless the level says otherwise
+    fn default_hits(&self) -> Option<u32> {
+        match self {
+            BrickKind::Standard => Some(1),
+            BrickKind::Reinforced => Some(3),
+            BrickKind::Unbreakable => None,
+        }
+    }
+
+    /// Points scored for breaking a brick of this kind
+    fn points(&self) -> usize {
+        match self {
+            BrickKind::Standard => 1,
+            BrickKind::Reinforced => 3,
+            BrickKind::Unbreakable => 0,
+        }
+    }
+}
+
+// How many more hits a breakable brick can take before it breaks
+#[derive(Component)]
+struct Health {
+    current: u32,
+    max: u32,
+}
+
+impl Health {
+    fn new(hits: u32) -> Self {
+        Health {
+            current: hits,
+            max: hits,
+        }
+    }
+
+    /// How much of the brick has been knocked off, from 0 (untouched) to 1 (broken)
+    fn damage(&self) -> f32 {
+        1.0 - self.current as f32 / self.max as f32
+    }
+}
+
+// The color a brick was given by its level, before any damage
+#[derive(Component, Deref)]
+struct BrickColor(Color);
+
+// Sent when a brick runs out of health and is despawned
+#[derive(Event)]
+struct BrickDestroyed {
+    position: Vec2,
+    kind: BrickKind,
+    row: usize,
+}
+
+// Mesh and material shared by every ball, so that new balls can be spawned at any time
+#[derive(Resource)]
+struct BallAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<ColorMaterial>,
+}
+
+impl BallAssets {
+    /// Components for a new ball in the arena
+    fn ball(&self, translation: Vec3, velocity: Vec2, config: &GameConfig) -> impl Bundle {
+        (
+            Mesh2d(self.mesh.clone()),
+            MeshMaterial2d(self.material.clone()),
+            Transform::from_translation(translation)
+                .with_scale(Vec2::splat(config.ball.diameter).extend(1.)),
+            Ball,
+            Velocity(velocity),
+            StateScoped(InGame),
+        )
+    }
+
+    /// Components for a fresh ball resting on the paddle, see [`StuckToPaddle`]
+    fn served_ball(&self, paddle_x: f32, config: &GameConfig) -> impl Bundle {
+        let offset = config.ball.serve_offset;
+        let y = config.paddle_y() + config.paddle.size.y / 2. + config.ball.diameter / 2.;
+        (
+            self.ball(Vec3::new(paddle_x + offset, y, BALL_Z), Vec2::ZERO, config),
+            StuckToPaddle { offset },
+        )
+    }
+}
 
 // This bundle is a collection of the components that define a "wall" in our game
 #[derive(Bundle)]
@@ -108,10 +461,16 @@ struct WallBundle {
     // Allowing you to compose their functionality
     sprite: Sprite,
     transform: Transform,
+    wall: Wall,
     collider: Collider,
 }
 
+// Keeps track of where each wall goes, so that they can follow changes to the config
+#[derive(Component, Deref)]
+struct Wall(WallLocation);
+
 /// Which side of the arena is this wall located on?
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum WallLocation {
     Left,
     Right,
@@ -121,50 +480,54 @@ enum WallLocation {
 
 impl WallLocation {
     /// Location of the *center* of the wall, used in `transform.translation()`
-    fn position(&self) -> Vec2 {
+    fn position(&self, arena: &ArenaConfig) -> Vec2 {
         match self {
-            WallLocation::Left => Vec2::new(LEFT_WALL, 0.),
-            WallLocation::Right => Vec2::new(RIGHT_WALL, 0.),
-            WallLocation::Bottom => Vec2::new(0., BOTTOM_WALL),
-            WallLocation::Top => Vec2::new(0., TOP_WALL),
+            WallLocation::Left => Vec2::new(arena.left, 0.),
+            WallLocation::Right => Vec2::new(arena.right, 0.),
+            WallLocation::Bottom => Vec2::new(0., arena.bottom),
+            WallLocation::Top => Vec2::new(0., arena.top),
         }
     }
 
     /// (x, y) dimensions of the wall, used in `transform.scale()`
-    fn size(&self) -> Vec2 {
-        let arena_height = TOP_WALL - BOTTOM_WALL;
-        let arena_width = RIGHT_WALL - LEFT_WALL;
-        // Make sure we haven't messed up our constants
-        assert!(arena_height > 0.0);
-        assert!(arena_width > 0.0);
+    fn size(&self, arena: &ArenaConfig) -> Vec2 {
+        // A config with the walls the wrong way round leaves an empty arena rather than crashing
+        let arena_height = (arena.top - arena.bottom).max(0.0);
+        let arena_width = (arena.right - arena.left).max(0.0);
 
         match self {
             WallLocation::Left | WallLocation::Right => {
-                Vec2::new(WALL_THICKNESS, arena_height + WALL_THICKNESS)
+                Vec2::new(arena.wall_thickness, arena_height + arena.wall_thickness)
             }
             WallLocation::Bottom | WallLocation::Top => {
-                Vec2::new(arena_width + WALL_THICKNESS, WALL_THICKNESS)
+                Vec2::new(arena_width + arena.wall_thickness, arena.wall_thickness)
             }
         }
     }
+
+    /// Transform placing and sizing a wall sprite at this location
+    fn transform(&self, arena: &ArenaConfig) -> Transform {
+        Transform {
+            // We need to convert our Vec2 into a Vec3, by giving it a z-coordinate
+            // This is used to determine the order of our sprites
+            translation: self.position(arena).extend(0.0),
+            // The z-scale of 2D objects must always be 1.0,
+            // or their ordering will be affected in surprising ways.
+            // See https://github.com/bevyengine/bevy/issues/4149
+            scale: self.size(arena).extend(1.0),
+            ..default()
+        }
+    }
 }
 
 impl WallBundle {
     // This "builder method" allows us to reuse logic across our wall entities,
     // making our code easier to read and less prone to bugs when we change the logic
-    fn new(location: WallLocation) -> WallBundle {
+    fn new(location: WallLocation, arena: &ArenaConfig) -> WallBundle {
         WallBundle {
-            sprite: Sprite::from_color(WALL_COLOR, Vec2::ONE),
-            transform: Transform {
-                // We need to convert our Vec2 into a Vec3, by giving it a z-coordinate
-                // This is used to determine the order of our sprites
-                translation: location.position().extend(0.0),
-                // The z-scale of 2D objects must always be 1.0,
-                // or their ordering will be affected in surprising ways.
-                // See https://github.com/bevyengine/bevy/issues/4149
-                scale: location.size().extend(1.0),
-                ..default()
-            },
+            sprite: Sprite::from_color(srgb(arena.wall_color), Vec2::ONE),
+            transform: location.transform(arena),
+            wall: Wall(location),
             collider: Collider,
         }
     }
@@ -174,46 +537,90 @@ impl WallBundle {
 #[derive(Resource, Deref, DerefMut)]
 struct Score(usize);
 
+// This resource tracks how many more times the ball may be lost before the game is over
+#[derive(Resource, Deref, DerefMut)]
+struct Lives(usize);
+
 #[derive(Component)]
 struct ScoreboardUi;
 
-// Add the game's entities to our world
+/// Tells the player how to launch a ball waiting on the paddle
+#[derive(Component)]
+struct ServeHint;
+
+// Set up what lives for the whole session, whether or not the game is being shown
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
 ) {
-    // Camera
+    // Ball
+    commands.insert_resource(BallAssets {
+        mesh: meshes.add(Circle::default()),
+        material: materials.add(srgb(config.ball.color)),
+    });
+}
+
+// Only needed when the game is actually shown
+fn setup_camera(mut commands: Commands) {
     commands.spawn(Camera2d);
+}
 
-    // Sound
-    let ball_collision_sound = asset_server.load("sounds/breakout_collision.ogg");
-    commands.insert_resource(CollisionSound(ball_collision_sound));
+// Add the game's entities to our world; the bricks are spawned by the `level` module.
+// Everything spawned here is scoped to `InGame`, so it is despawned when we return to the menu.
+fn spawn_arena(
+    mut commands: Commands,
+    ball_assets: Res<BallAssets>,
+    config: Res<GameConfig>,
+    bindings: Res<InputBindings>,
+    mut score: ResMut<Score>,
+    mut lives: ResMut<Lives>,
+) {
+    // A new game always starts from zero
+    **score = 0;
+    **lives = config.lives;
 
     // Paddle
-    let paddle_y = BOTTOM_WALL + GAP_BETWEEN_PADDLE_AND_FLOOR;
-
     commands.spawn((
-        Sprite::from_color(PADDLE_COLOR, Vec2::ONE),
+        Sprite::from_color(srgb(config.paddle.color), Vec2::ONE),
         Transform {
-            translation: Vec3::new(0.0, paddle_y, 0.0),
-            scale: PADDLE_SIZE.extend(1.0),
+            translation: Vec3::new(0.0, config.paddle_y(), 0.0),
+            scale: config.paddle.size.extend(1.0),
             ..default()
         },
         Paddle,
         Collider,
+        StateScoped(InGame),
     ));
 
     // Ball
-    commands.spawn((
-        Mesh2d(meshes.add(Circle::default())),
-        MeshMaterial2d(materials.add(BALL_COLOR)),
-        Transform::from_translation(BALL_STARTING_POSITION)
-            .with_scale(Vec2::splat(BALL_DIAMETER).extend(1.)),
-        Ball,
-        Velocity(INITIAL_BALL_DIRECTION.normalize() * BALL_SPEED),
-    ));
+    commands.spawn(ball_assets.served_ball(0.0, &config));
+    let launch = bindings
+        .get(Action::Launch)
+        .first()
+        .map_or_else(|| "Launch".to_string(), ToString::to_string);
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                bottom: SERVE_HINT_BOTTOM,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ServeHint,
+            Visibility::Hidden,
+            StateScoped(InGame),
+        ))
+        .with_child((
+            Text::new(format!("Press {launch} to launch")),
+            TextFont {
+                font_size: SERVE_HINT_FONT_SIZE,
+                ..default()
+            },
+            TextColor(TEXT_COLOR),
+        ));
 
     // Scoreboard
     commands
@@ -225,6 +632,7 @@ fn setup(
             },
             TextColor(TEXT_COLOR),
             ScoreboardUi,
+            StateScoped(InGame),
             Node {
                 position_type: PositionType::Absolute,
                 top: SCOREBOARD_TEXT_PADDING,
@@ -232,6 +640,54 @@ fn setup(
                 ..default()
             },
         ))
+        .with_child((
+            TextSpan::default(),
+            TextFont {
+                font_size: SCOREBOARD_FONT_SIZE,
+                ..default()
+            },
+            TextColor(SCORE_COLOR),
+        ))
+        .with_child((
+            TextSpan::new("   Lives: "),
+            TextFont {
+                font_size: SCOREBOARD_FONT_SIZE,
+                ..default()
+            },
+            TextColor(TEXT_COLOR),
+        ))
+        .with_child((
+            TextSpan::default(),
+            TextFont {
+                font_size: SCOREBOARD_FONT_SIZE,
+                ..default()
+            },
+            TextColor(SCORE_COLOR),
+        ))
+        .with_child((
+            TextSpan::new("   Combo: "),
+            TextFont {
+                font_size: SCOREBOARD_FONT_SIZE,
+                ..default()
+            },
+            TextColor(TEXT_COLOR),
+        ))
+        .with_child((
+            TextSpan::default(),
+            TextFont {
+                font_size: SCOREBOARD_FONT_SIZE,
+                ..default()
+            },
+            TextColor(SCORE_COLOR),
+        ))
+        .with_child((
+            TextSpan::new("   Difficulty: "),
+            TextFont {
+                font_size: SCOREBOARD_FONT_SIZE,
+                ..default()
+            },
+            TextColor(TEXT_COLOR),
+        ))
         .with_child((
             TextSpan::default(),
             TextFont {
@@ -242,172 +698,468 @@ fn setup(
         ));
 
     // Walls
-    commands.spawn(WallBundle::new(WallLocation::Left));
-    commands.spawn(WallBundle::new(WallLocation::Right));
-    commands.spawn(WallBundle::new(WallLocation::Bottom));
-    commands.spawn(WallBundle::new(WallLocation::Top));
-
-    // Bricks
-    let total_width_of_bricks = (RIGHT_WALL - LEFT_WALL) - 2. * GAP_BETWEEN_BRICKS_AND_SIDES;
-    let bottom_edge_of_bricks = paddle_y + GAP_BETWEEN_PADDLE_AND_BRICKS;
-    let total_height_of_bricks = TOP_WALL - bottom_edge_of_bricks - GAP_BETWEEN_BRICKS_AND_CEILING;
-
-    assert!(total_width_of_bricks > 0.0);
-    assert!(total_height_of_bricks > 0.0);
-
-    // Given the space available, compute how many rows and columns of bricks we can fit
-    let n_columns = (total_width_of_bricks / (BRICK_SIZE.x + GAP_BETWEEN_BRICKS)).floor() as usize;
-    let n_rows = (total_height_of_bricks / (BRICK_SIZE.y + GAP_BETWEEN_BRICKS)).floor() as usize;
-    let n_vertical_gaps = n_columns - 1;
-
-    // Because we need to round the number of columns,
-    // the space on the top and sides of the bricks only captures a lower bound, not an exact value
-    let center_of_bricks = (LEFT_WALL + RIGHT_WALL) / 2.0;
-    let left_edge_of_bricks = center_of_bricks
-        // Space taken up by the bricks
-        - (n_columns as f32 / 2.0 * BRICK_SIZE.x)
-        // Space taken up by the gaps
-        - n_vertical_gaps as f32 / 2.0 * GAP_BETWEEN_BRICKS;
-
-    // In Bevy, the `translation` of an entity describes the center point,
-    // not its bottom-left corner
-    let offset_x = left_edge_of_bricks + BRICK_SIZE.x / 2.;
-    let offset_y = bottom_edge_of_bricks + BRICK_SIZE.y / 2.;
-
-    for row in 0..n_rows {
-        for column in 0..n_columns {
-            let brick_position = Vec2::new(
-                offset_x + column as f32 * (BRICK_SIZE.x + GAP_BETWEEN_BRICKS),
-                offset_y + row as f32 * (BRICK_SIZE.y + GAP_BETWEEN_BRICKS),
-            );
+    let arena = &config.arena;
+    commands.spawn((
+        WallBundle::new(WallLocation::Left, arena),
+        StateScoped(InGame),
+    ));
+    commands.spawn((
+        WallBundle::new(WallLocation::Right, arena),
+        StateScoped(InGame),
+    ));
+    commands.spawn((
+        WallBundle::new(WallLocation::Top, arena),
+        StateScoped(InGame),
+    ));
 
-            // brick
-            commands.spawn((
-                Sprite {
-                    color: BRICK_COLOR,
-                    ..default()
-                },
-                Transform {
-                    translation: brick_position.extend(0.0),
-                    scale: Vec3::new(BRICK_SIZE.x, BRICK_SIZE.y, 1.0),
-                    ..default()
-                },
-                Brick,
-                Collider,
-            ));
-        }
-    }
+    // The floor looks like a wall, but it isn't a collider: touching it costs a life
+    commands.spawn((
+        Sprite::from_color(srgb(arena.wall_color), Vec2::ONE),
+        WallLocation::Bottom.transform(arena),
+        Wall(WallLocation::Bottom),
+        KillZone,
+        StateScoped(InGame),
+    ));
 }
 
 fn move_paddle(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    command: Res<PaddleCommand>,
     mut paddle_transform: Single<&mut Transform, With<Paddle>>,
+    config: Res<GameConfig>,
     time: Res<Time>,
 ) {
-    let mut direction = 0.0;
-
-    if keyboard_input.pressed(KeyCode::ArrowLeft) {
-        direction -= 1.0;
-    }
-
-    if keyboard_input.pressed(KeyCode::ArrowRight) {
-        direction += 1.0;
-    }
+    let max_distance = config.paddle.speed * time.delta_secs();
+    let distance = match command.target {
+        // Head for the pointer at full speed, without overshooting it
+        Some(target) => {
+            (target - paddle_transform.translation.x).clamp(-max_distance, max_distance)
+        }
+        None => command.direction.clamp(-1.0, 1.0) * max_distance,
+    };
 
     // Calculate the new horizontal paddle position based on player input
-    let new_paddle_position =
-        paddle_transform.translation.x + direction * PADDLE_SPEED * time.delta_secs();
+    let new_paddle_position = paddle_transform.translation.x + distance;
 
     // Update the paddle position,
-    // making sure it doesn't cause the paddle to leave the arena
-    let left_bound = LEFT_WALL + WALL_THICKNESS / 2.0 + PADDLE_SIZE.x / 2.0 + PADDLE_PADDING;
-    let right_bound = RIGHT_WALL - WALL_THICKNESS / 2.0 - PADDLE_SIZE.x / 2.0 - PADDLE_PADDING;
+    // making sure it doesn't cause the paddle to leave the arena.
+    // Power-ups can change the paddle's width, so go by its current size.
+    let arena = &config.arena;
+    let half_width = paddle_transform.scale.x / 2.0;
+    let left_bound = arena.left + arena.wall_thickness / 2.0 + half_width + config.paddle.padding;
+    let right_bound = arena.right - arena.wall_thickness / 2.0 - half_width - config.paddle.padding;
 
-    paddle_transform.translation.x = new_paddle_position.clamp(left_bound, right_bound);
+    // A paddle wider than the arena stays in the middle
+    paddle_transform.translation.x = if left_bound <= right_bound {
+        new_paddle_position.clamp(left_bound, right_bound)
+    } else {
+        (arena.left + arena.right) / 2.0
+    };
 }
 
-fn apply_velocity(mut query: Query<(&mut Transform, &Velocity)>, time: Res<Time>) {
+/// Carry stuck balls along with the paddle
+fn follow_paddle(
+    mut balls: Query<(&mut Transform, &StuckToPaddle), With<Ball>>,
+    paddle_transform: Single<&Transform, (With<Paddle>, Without<Ball>)>,
+) {
+    for (mut transform, stuck) in &mut balls {
+        transform.translation.x = paddle_transform.translation.x + stuck.offset;
+        transform.translation.y =
+            paddle_transform.translation.y + paddle_transform.scale.y / 2. + transform.scale.y / 2.;
+    }
+}
+
+/// Send stuck balls off when the player asks to, aimed as if they had bounced where they sit
+fn launch_stuck_balls(
+    mut commands: Commands,
+    command: Res<PaddleCommand>,
+    mut balls: Query<(Entity, &mut Velocity, &StuckToPaddle), With<Ball>>,
+    paddle_transform: Single<&Transform, With<Paddle>>,
+    speed: Res<BallSpeed>,
+    config: Res<GameConfig>,
+) {
+    if !command.launch {
+        return;
+    }
+    for (entity, mut velocity, stuck) in &mut balls {
+        **velocity = paddle_bounce(
+            stuck.offset,
+            paddle_transform.scale.x,
+            speed.current,
+            config.ball.max_bounce_angle,
+        );
+        commands.entity(entity).remove::<StuckToPaddle>();
+    }
+}
+
+// The ball is moved by `check_for_collisions` instead, which needs to follow its path exactly
+fn apply_velocity(mut query: Query<(&mut Transform, &Velocity), Without<Ball>>, time: Res<Time>) {
     for (mut transform, velocity) in &mut query {
         transform.translation.x += velocity.x * time.delta_secs();
         transform.translation.y += velocity.y * time.delta_secs();
     }
 }
 
+/// Only show the serve hint while a ball is waiting to be launched
+fn update_serve_hint(
+    stuck_balls: Query<(), (With<Ball>, With<StuckToPaddle>)>,
+    mut hint_visibility: Single<&mut Visibility, With<ServeHint>>,
+) {
+    let visibility = if stuck_balls.is_empty() {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    hint_visibility.set_if_neq(visibility);
+}
+
+#[allow(clippy::too_many_arguments)]
 fn update_scoreboard(
     score: Res<Score>,
+    lives: Res<Lives>,
+    combo: Res<Combo>,
+    difficulty: Res<Difficulty>,
+    speed: Res<BallSpeed>,
+    config: Res<GameConfig>,
     score_root: Single<Entity, (With<ScoreboardUi>, With<Text>)>,
     mut writer: TextUiWriter,
 ) {
     *writer.text(*score_root, 1) = score.to_string();
+    *writer.text(*score_root, 3) = lives.to_string();
+    *writer.text(*score_root, 5) = format!("x{}", combo.multiplier(&config));
+    // Along with how far the balls have sped up
+    *writer.text(*score_root, 7) =
+        format!("{difficulty:?} (speed x{:.2})", speed.multiple(&config));
 }
 
 fn check_for_collisions(
     mut commands: Commands,
-    mut score: ResMut<Score>,
-    ball_query: Single<(&mut Velocity, &Transform), With<Ball>>,
-    collider_query: Query<(Entity, &Transform, Option<&Brick>), With<Collider>>,
+    mut ball_query: Query<
+        (Entity, &mut Velocity, &mut Transform),
+        (With<Ball>, Without<StuckToPaddle>),
+    >,
+    mut collider_query: Query<
+        (
+            Entity,
+            &Transform,
+            Has<Paddle>,
+            Has<Sticky>,
+            Has<KillZone>,
+            Option<&BrickKind>,
+            Option<&BrickRow>,
+            Option<&Wall>,
+            Option<&mut Health>,
+        ),
+        (Or<(With<Collider>, With<KillZone>)>, Without<Ball>),
+    >,
     mut collision_events: EventWriter<CollisionEvent>,
+    mut ball_lost_events: EventWriter<BallLost>,
+    config: Res<GameConfig>,
+    time: Res<Time>,
 ) {
-    let (mut ball_velocity, ball_transform) = ball_query.into_inner();
-
-    for (collider_entity, collider_transform, maybe_brick) in &collider_query {
-        let collision = ball_collision(
-            BoundingCircle::new(ball_transform.translation.truncate(), BALL_DIAMETER / 2.),
-            Aabb2d::new(
-                collider_transform.translation.truncate(),
-                collider_transform.scale.truncate() / 2.,
-            ),
-        );
+    // Balls can compete for the same brick, so handle them in a fixed order
+    let mut balls: Vec<_> = ball_query.iter_mut().collect();
+    balls.sort_by(
+        |(_, a_velocity, a_transform), (_, b_velocity, b_transform)| {
+            spatial_order(
+                a_transform.translation.truncate(),
+                b_transform.translation.truncate(),
+            )
+            .then(spatial_order(***a_velocity, ***b_velocity))
+        },
+    );
 
-        if let Some(collision) = collision {
-            // Sends a collision event so that other systems can react to the collision
-            collision_events.send_default();
+    for (ball_entity, mut ball_velocity, mut ball_transform) in balls {
+        // The ball is moved here rather than in `apply_velocity`: sweeping it along its path means
+        // it can't tunnel through thin walls or bricks, however far it travels in a single step
+        let mut remaining_time = time.delta_secs();
+        for _ in 0..MAX_BOUNCES_PER_STEP {
+            let ball = BoundingCircle::new(
+                ball_transform.translation.truncate(),
+                ball_transform.scale.x / 2.,
+            );
+            let motion = **ball_velocity * remaining_time;
+
+            // Find the first collider the ball would touch on its way,
+            // ignoring bricks that have already been broken during this step
+            let first_contact = collider_query
+                .iter()
+                .filter(|(.., maybe_health)| {
+                    maybe_health
+                        .as_ref()
+                        .is_none_or(|health| health.current > 0)
+                })
+                .filter_map(|(collider_entity, collider_transform, ..)| {
+                    let contact = ball_sweep(
+                        ball,
+                        motion,
+                        Aabb2d::new(
+                            collider_transform.translation.truncate(),
+                            collider_transform.scale.truncate() / 2.,
+                        ),
+                    )?;
+                    Some((collider_entity, collider_transform.translation, contact))
+                })
+                // A ball touching two colliders at once bounces off the one furthest left, then lowest
+                .min_by(|(_, a_position, a), (_, b_position, b)| {
+                    a.time
+                        .total_cmp(&b.time)
+                        .then(spatial_order(a_position.truncate(), b_position.truncate()))
+                });
 
-            // Bricks should be despawned and increment the scoreboard on collision
-            if maybe_brick.is_some() {
-                commands.entity(collider_entity).despawn();
-                **score += 1;
+            let Some((collider_entity, _, contact)) = first_contact else {
+                // Nothing in the way, so the ball can travel the rest of the step freely
+                ball_transform.translation += motion.extend(0.0);
+                break;
+            };
+
+            // Move the ball up to the point of contact, and spend the rest of the step bouncing off
+            ball_transform.translation += (motion * contact.time).extend(0.0);
+            remaining_time *= 1.0 - contact.time;
+
+            let Ok((
+                _,
+                collider_transform,
+                is_paddle,
+                is_sticky,
+                is_kill_zone,
+                maybe_kind,
+                maybe_row,
+                maybe_wall,
+                maybe_health,
+            )) = collider_query.get_mut(collider_entity)
+            else {
+                break;
+            };
+
+            // Touching a kill zone ends the ball's movement altogether
+            if is_kill_zone {
+                ball_lost_events.send(BallLost(ball_entity));
+                break;
             }
 
-            // Reflect the ball's velocity when it collides
-            let mut reflect_x = false;
-            let mut reflect_y = false;
-
-            // Reflect only if the velocity is in the opposite direction of the collision
-            // This prevents the ball from getting stuck inside the bar
-            match collision {
-                Collision::Left => reflect_x = ball_velocity.x > 0.0,
-                Collision::Right => reflect_x = ball_velocity.x < 0.0,
-                Collision::Top => reflect_y = ball_velocity.y < 0.0,
-                Collision::Bottom => reflect_y = ball_velocity.y > 0.0,
+            // Breakable bricks lose a hit point on collision, see `break_bricks`
+            let mut destroyed = false;
+            if let Some(mut health) = maybe_health {
+                health.current = health.current.saturating_sub(1);
+                destroyed = health.current == 0;
             }
 
-            // Reflect velocity on the x-axis if we hit something on the x-axis
-            if reflect_x {
-                ball_velocity.x = -ball_velocity.x;
+            let velocity_before = **ball_velocity;
+            let mut stuck = false;
+            if is_paddle && contact.side == Collision::Top && ball_velocity.y < 0.0 {
+                let offset = ball_transform.translation.x - collider_transform.translation.x;
+
+                if is_sticky {
+                    // A sticky paddle holds on to the ball until the player launches it
+                    **ball_velocity = Vec2::ZERO;
+                    commands
+                        .entity(ball_entity)
+                        .insert(StuckToPaddle { offset });
+                    stuck = true;
+                } else {
+                    // Bouncing off the top of the paddle sends the ball off at an angle
+                    // that depends on where it landed, which is how the player aims
+                    **ball_velocity = paddle_bounce(
+                        offset,
+                        collider_transform.scale.x,
+                        ball_velocity.length(),
+                        config.ball.max_bounce_angle,
+                    );
+                }
+            } else {
+                // Reflect the ball's velocity off the surface it hit.
+                // Reflect only if the velocity is in the opposite direction of the collision
+                // This prevents the ball from getting stuck inside the bar
+                let approach_speed = ball_velocity.dot(contact.normal);
+                if approach_speed < 0.0 {
+                    **ball_velocity -= 2.0 * approach_speed * contact.normal;
+                }
             }
 
-            // Reflect velocity on the y-axis if we hit something on the y-axis
-            if reflect_y {
-                ball_velocity.y = -ball_velocity.y;
+            // Sends a collision event so that other systems can react to the collision
+            let hit = match (maybe_kind, maybe_row, maybe_wall) {
+                (Some(&kind), Some(&BrickRow(row)), _) => Hit::Brick {
+                    kind,
+                    row,
+                    destroyed,
+                },
+                (.., Some(&Wall(location))) => Hit::Wall(location),
+                // Whatever else has a collider is the paddle
+                _ => Hit::Paddle,
+            };
+            collision_events.send(CollisionEvent {
+                ball: ball_entity,
+                collider: collider_entity,
+                hit,
+                point: ball_transform.translation.truncate() - contact.normal * ball.radius(),
+                side: contact.side,
+                normal: contact.normal,
+                velocity_before,
+                velocity_after: **ball_velocity,
+            });
+
+            if stuck {
+                break;
             }
         }
     }
 }
 
-fn play_collision_sound(
+// Every bounce in full, shown with `RUST_LOG=mygame=debug`
+fn log_collisions(mut collision_events: EventReader<CollisionEvent>) {
+    for event in collision_events.read() {
+        debug!(
+            "ball {} hit {:?} {} on its {:?} side at {} with normal {}, velocity {} -> {}",
+            event.ball,
+            event.hit,
+            event.collider,
+            event.side,
+            event.point,
+            event.normal,
+            event.velocity_before,
+            event.velocity_after
+        );
+    }
+}
+
+// Bricks that ran out of health are despawned, and scored by the `scoring` module
+fn break_bricks(
+    mut commands: Commands,
+    bricks: Query<(Entity, &Transform, &BrickKind, &BrickRow, &Health), Changed<Health>>,
+    mut brick_destroyed_events: EventWriter<BrickDestroyed>,
+) {
+    // Sorted so that the power-up drops are rolled for in the same order every time
+    let mut broken: Vec<_> = bricks
+        .iter()
+        .filter(|(.., health)| health.current == 0)
+        .collect();
+    broken.sort_by(|(_, a, ..), (_, b, ..)| {
+        spatial_order(a.translation.truncate(), b.translation.truncate())
+    });
+
+    for (entity, transform, kind, row, _) in broken {
+        commands.entity(entity).despawn();
+        brick_destroyed_events.send(BrickDestroyed {
+            position: transform.translation.truncate(),
+            kind: *kind,
+            row: **row,
+        });
+    }
+}
+
+// Balls that reach the floor are removed from play.
+// When the last one goes the player loses a life,
+// and a new ball is served above the paddle until there are no lives left.
+#[allow(clippy::too_many_arguments)]
+fn check_for_ball_lost(
     mut commands: Commands,
-    mut collision_events: EventReader<CollisionEvent>,
-    sound: Res<CollisionSound>,
+    mut ball_lost_events: EventReader<BallLost>,
+    ball_query: Query<(), With<Ball>>,
+    paddle_transform: Single<&Transform, With<Paddle>>,
+    ball_assets: Res<BallAssets>,
+    config: Res<GameConfig>,
+    mut lives: ResMut<Lives>,
+    mut life_lost_events: EventWriter<LifeLost>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let mut lost_balls: Vec<Entity> = ball_lost_events.read().map(|event| event.0).collect();
+    if lost_balls.is_empty() {
+        return;
+    }
+    lost_balls.sort();
+    lost_balls.dedup();
+
+    for ball in &lost_balls {
+        commands.entity(*ball).despawn();
+    }
+
+    // The player only loses a life once every ball in play is gone
+    if ball_query.iter().count() > lost_balls.len() {
+        return;
+    }
+
+    **lives = lives.saturating_sub(1);
+    life_lost_events.send(LifeLost);
+    if **lives == 0 {
+        next_state.set(GameState::GameOver);
+        return;
+    }
+
+    commands.spawn(ball_assets.served_ball(paddle_transform.translation.x, &config));
+}
+
+// Fade damaged bricks towards the background so the player can tell how close they are to breaking
+fn update_brick_damage(
+    mut bricks: Query<(&mut Sprite, &BrickColor, &Health), Changed<Health>>,
+    config: Res<GameConfig>,
+) {
+    let background = srgb(config.arena.background_color);
+    for (mut sprite, color, health) in &mut bricks {
+        sprite.color = color.mix(&background, health.damage() * config.bricks.damage_fade);
+    }
+}
+
+// Once the last breakable brick is gone the level is won
+fn check_for_level_cleared(
+    bricks: Query<&BrickKind, With<Brick>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if bricks.iter().all(|kind| *kind == BrickKind::Unbreakable) {
+        next_state.set(GameState::LevelComplete);
+    }
+}
+
+// Bring everything already on screen in line with a changed config.
+// Bricks keep their size and place until the next level is spawned.
+fn apply_config(
+    config: Res<GameConfig>,
+    mut clear_color: ResMut<ClearColor>,
+    mut walls: Query<(&Wall, &mut Transform, &mut Sprite), (Without<Paddle>, Without<Ball>)>,
+    mut paddles: Query<(&mut Transform, &mut Sprite), (With<Paddle>, Without<Ball>)>,
+    mut balls: Query<&mut Transform, With<Ball>>,
+    ball_assets: Res<BallAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    // Play a sound once per frame if a collision occurred.
-    if !collision_events.is_empty() {
-        // This prevents events staying active on the next frame.
-        collision_events.clear();
-        commands.spawn((AudioPlayer(sound.clone()), PlaybackSettings::DESPAWN));
+    clear_color.0 = srgb(config.arena.background_color);
+
+    for (wall, mut transform, mut sprite) in &mut walls {
+        *transform = wall.transform(&config.arena);
+        sprite.color = srgb(config.arena.wall_color);
+    }
+
+    // The paddle's width is kept up to date by the power-ups, see `powerup::update_paddle_effects`
+    for (mut transform, mut sprite) in &mut paddles {
+        transform.translation.y = config.paddle_y();
+        transform.scale.y = config.paddle.size.y;
+        sprite.color = srgb(config.paddle.color);
+    }
+
+    for mut transform in &mut balls {
+        transform.scale = Vec2::splat(config.ball.diameter).extend(1.);
+    }
+    if let Some(material) = materials.get_mut(&ball_assets.material) {
+        material.color = srgb(config.ball.color);
     }
 }
 
+// Orders positions from left to right, then from bottom to top.
+// Wherever it matters which entity is handled first, they are sorted by this rather than
+// by query order, which depends on how the entities happen to be stored.
+// That keeps replays exact, see the `replay` module.
+fn spatial_order(a: Vec2, b: Vec2) -> Ordering {
+    a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y))
+}
+
+// Returns the velocity of a ball leaving the paddle.
+// `offset` is the horizontal distance from the center of the paddle to the ball:
+// the center sends the ball straight up and the edges send it off at `max_angle` degrees.
+fn paddle_bounce(offset: f32, paddle_width: f32, speed: f32, max_angle: f32) -> Vec2 {
+    let hit_position = (offset / (paddle_width / 2.)).clamp(-1.0, 1.0);
+    let angle = hit_position * max_angle.to_radians();
+    // Angles are measured from straight up, so positive angles go to the right
+    Vec2::new(angle.sin(), angle.cos()) * speed
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 enum Collision {
     Left,
@@ -416,6 +1168,137 @@ enum Collision {
     Bottom,
 }
 
+impl Collision {
+    /// Unit vector pointing out of the side of the bounding box that was hit
+    fn normal(&self) -> Vec2 {
+        match self {
+            Collision::Left => Vec2::NEG_X,
+            Collision::Right => Vec2::X,
+            Collision::Top => Vec2::Y,
+            Collision::Bottom => Vec2::NEG_Y,
+        }
+    }
+
+    /// The side of a bounding box that faces most towards `normal`
+    fn from_normal(normal: Vec2) -> Collision {
+        if normal.x.abs() > normal.y.abs() {
+            if normal.x < 0. {
+                Collision::Left
+            } else {
+                Collision::Right
+            }
+        } else if normal.y > 0. {
+            Collision::Top
+        } else {
+            Collision::Bottom
+        }
+    }
+}
+
+/// Where and how a moving ball first touches a bounding box
+#[derive(Debug, Clone, Copy)]
+struct Contact {
+    /// Fraction of the ball's motion travelled before touching, from 0 to 1
+    time: f32,
+    /// The side of the bounding box that was hit
+    side: Collision,
+    /// Surface normal at the point of contact, pointing towards the ball.
+    /// Differs from `side.normal()` when the ball clips a corner.
+    normal: Vec2,
+}
+
+// Returns `Some` if `ball` touches `bounding_box` while moving by `motion`.
+// Unlike `ball_collision` this finds the earliest time of impact along the way,
+// so a fast ball can't skip over a thin collider between two steps.
+fn ball_sweep(ball: BoundingCircle, motion: Vec2, bounding_box: Aabb2d) -> Option<Contact> {
+    // A ball that already overlaps is resolved straight away, unless it is on its way out
+    if let Some(side) = ball_collision(ball, bounding_box) {
+        let normal = side.normal();
+        return (motion.dot(normal) < 0.0).then_some(Contact {
+            time: 0.0,
+            side,
+            normal,
+        });
+    }
+    // Otherwise a ball that isn't moving can't touch anything
+    if motion == Vec2::ZERO {
+        return None;
+    }
+
+    // The ball's center touches the bounding box grown by the ball's radius.
+    // Start by casting a ray from the center against that grown box, one axis at a time.
+    let radius = ball.radius();
+    let start = ball.center();
+    let grown_min = bounding_box.min - radius;
+    let grown_max = bounding_box.max + radius;
+
+    let mut time_enter = f32::NEG_INFINITY;
+    let mut time_exit = f32::INFINITY;
+    let mut normal = Vec2::ZERO;
+    for axis in 0..2 {
+        if motion[axis] == 0.0 {
+            // Moving parallel to this pair of sides, which can only be hit from in between them
+            if start[axis] < grown_min[axis] || start[axis] > grown_max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let time_to_min = (grown_min[axis] - start[axis]) / motion[axis];
+        let time_to_max = (grown_max[axis] - start[axis]) / motion[axis];
+        let (near, far) = if time_to_min < time_to_max {
+            (time_to_min, time_to_max)
+        } else {
+            (time_to_max, time_to_min)
+        };
+
+        if near > time_enter {
+            time_enter = near;
+            normal = Vec2::ZERO;
+            normal[axis] = -motion[axis].signum();
+        }
+        time_exit = time_exit.min(far);
+    }
+
+    if time_enter > time_exit || time_enter > 1.0 || time_exit < 0.0 {
+        return None;
+    }
+
+    // The grown box has square corners where the real shape is rounded.
+    // If the ray enters through one of them, cast it against the circle around that corner instead.
+    let entry = start + motion * time_enter;
+    let outside_x = entry.x < bounding_box.min.x || entry.x > bounding_box.max.x;
+    let outside_y = entry.y < bounding_box.min.y || entry.y > bounding_box.max.y;
+    if outside_x && outside_y {
+        let corner = entry.clamp(bounding_box.min, bounding_box.max);
+        let offset = start - corner;
+        // Solve |offset + motion * t| = radius for the smallest t
+        let a = motion.length_squared();
+        let b = offset.dot(motion);
+        let c = offset.length_squared() - radius * radius;
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let time = (-b - discriminant.sqrt()) / a;
+        if !(0.0..=1.0).contains(&time) {
+            return None;
+        }
+        let normal = (offset + motion * time) / radius;
+        return Some(Contact {
+            time,
+            side: Collision::from_normal(normal),
+            normal,
+        });
+    }
+
+    Some(Contact {
+        time: time_enter.max(0.0),
+        side: Collision::from_normal(normal),
+        normal,
+    })
+}
+
 // Returns `Some` if `ball` collides with `bounding_box`.
 // The returned `Collision` is the side of `bounding_box` that `ball` hit.
 fn ball_collision(ball: BoundingCircle, bounding_box: Aabb2d) -> Option<Collision> {
@@ -439,3 +1322,407 @@ fn ball_collision(ball: BoundingCircle, bounding_box: Aabb2d) -> Option<Collisio
 
     Some(side)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::{event::Events, system::RunSystemOnce};
+
+    use super::*;
+
+    const BALL_RADIUS: f32 = 15.0;
+    const STEP: f32 = 1.0 / 64.0;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    fn assert_direction(velocity: Vec2, expected: Vec2) {
+        let (direction, expected) = (velocity.normalize(), expected.normalize());
+        assert_close(direction.x, expected.x);
+        assert_close(direction.y, expected.y);
+    }
+
+    fn ball(x: f32, y: f32) -> BoundingCircle {
+        BoundingCircle::new(Vec2::new(x, y), BALL_RADIUS)
+    }
+
+    // A world with just what `check_for_collisions` needs, one fixed step long
+    fn collision_world() -> World {
+        let mut world = World::new();
+        world.insert_resource(GameConfig::default());
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f32(STEP));
+        world.insert_resource(time);
+        world.init_resource::<Events<CollisionEvent>>();
+        world.init_resource::<Events<BallLost>>();
+        world
+    }
+
+    fn spawn_ball(world: &mut World, position: Vec2, velocity: Vec2) -> Entity {
+        world
+            .spawn((
+                Ball,
+                Velocity(velocity),
+                Transform::from_translation(position.extend(BALL_Z))
+                    .with_scale(Vec2::splat(BALL_RADIUS * 2.).extend(1.)),
+            ))
+            .id()
+    }
+
+    fn spawn_wall(world: &mut World, location: WallLocation, center: Vec2, size: Vec2) -> Entity {
+        world
+            .spawn((
+                Wall(location),
+                Collider,
+                Transform::from_translation(center.extend(0.)).with_scale(size.extend(1.)),
+            ))
+            .id()
+    }
+
+    fn spawn_brick(world: &mut World, center: Vec2) -> Entity {
+        world
+            .spawn((
+                Brick,
+                BrickKind::Standard,
+                BrickRow(0),
+                Health::new(1),
+                Collider,
+                Transform::from_translation(center.extend(0.))
+                    .with_scale(Vec2::new(100., 30.).extend(1.)),
+            ))
+            .id()
+    }
+
+    fn run_collisions(world: &mut World) -> Vec<CollisionEvent> {
+        world.run_system_once(check_for_collisions).unwrap();
+        world
+            .resource_mut::<Events<CollisionEvent>>()
+            .drain()
+            .collect()
+    }
+
+    #[test]
+    fn fast_ball_does_not_tunnel_through_a_wall() {
+        let arena = ArenaConfig::default();
+        let wall = WallLocation::Right.transform(&arena);
+        let wall = Aabb2d::new(wall.translation.truncate(), wall.scale.truncate() / 2.);
+        // Twenty times the default speed covers more than the gap, the wall and the ball together
+        let start = ball(400., 0.);
+        let motion = Vec2::new(20. * GameConfig::default().ball.speed * STEP, 0.);
+        assert!(ball_collision(ball(400. + motion.x, 0.), wall).is_none());
+
+        let contact = ball_sweep(start, motion, wall).expect("the ball should hit the wall");
+        assert_eq!(contact.side, Collision::Left);
+        assert_eq!(contact.normal, Vec2::NEG_X);
+        // The wall's inner face is at 445, so the ball's edge has 30 to travel
+        assert_close(contact.time, 30. / motion.x);
+    }
+
+    #[test]
+    fn corner_hit_reflects_off_the_corner() {
+        let brick = Aabb2d::new(Vec2::ZERO, Vec2::new(50., 10.));
+        let contact = ball_sweep(ball(80., 40.), Vec2::new(-40., -40.), brick)
+            .expect("the ball should clip the corner");
+
+        let corner_direction = Vec2::ONE.normalize();
+        assert_close(contact.normal.x, corner_direction.x);
+        assert_close(contact.normal.y, corner_direction.y);
+        assert_ne!(contact.normal, contact.side.normal());
+        // The ball's center is one radius away from the corner when it touches
+        assert_close(contact.time, (30. - BALL_RADIUS / 2f32.sqrt()) / 40.);
+    }
+
+    #[test]
+    fn overlapping_ball_moving_away_is_left_alone() {
+        let brick = Aabb2d::new(Vec2::ZERO, Vec2::new(50., 10.));
+        assert!(ball_collision(ball(0., 20.), brick).is_some());
+        assert!(ball_sweep(ball(0., 20.), Vec2::new(0., 10.), brick).is_none());
+    }
+
+    #[test]
+    fn overlapping_ball_moving_in_is_hit_straight_away() {
+        let brick = Aabb2d::new(Vec2::ZERO, Vec2::new(50., 10.));
+        let contact = ball_sweep(ball(0., 20.), Vec2::new(0., -10.), brick)
+            .expect("the ball should hit the brick");
+        assert_eq!(contact.time, 0.0);
+        assert_eq!(contact.side, Collision::Top);
+        assert_eq!(contact.normal, Vec2::Y);
+    }
+
+    #[test]
+    fn ball_at_rest_near_a_corner_touches_nothing() {
+        // Inside the box grown by the ball's radius, but clear of the rounded corner
+        let brick = Aabb2d::new(Vec2::ZERO, Vec2::new(50., 10.));
+        assert!(ball_collision(ball(62., 22.), brick).is_none());
+        assert!(ball_sweep(ball(62., 22.), Vec2::ZERO, brick).is_none());
+    }
+
+    #[test]
+    fn only_the_first_of_two_bricks_in_the_way_is_hit() {
+        let half_size = Vec2::new(50., 15.);
+        let near = Aabb2d::new(Vec2::new(0., 100.), half_size);
+        let far = Aabb2d::new(Vec2::new(0., 140.), half_size);
+        let motion = Vec2::new(0., 200.);
+        let near_time = ball_sweep(ball(0., 0.), motion, near).unwrap().time;
+        let far_time = ball_sweep(ball(0., 0.), motion, far).unwrap().time;
+        assert!(near_time < far_time);
+
+        let mut world = collision_world();
+        let ball = spawn_ball(&mut world, Vec2::ZERO, motion / STEP);
+        let near = spawn_brick(&mut world, Vec2::new(0., 100.));
+        let far = spawn_brick(&mut world, Vec2::new(0., 140.));
+
+        let events = run_collisions(&mut world);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].collider, near);
+        assert_eq!(
+            events[0].hit,
+            Hit::Brick {
+                kind: BrickKind::Standard,
+                row: 0,
+                destroyed: true,
+            }
+        );
+        assert_eq!(world.get::<Health>(near).unwrap().current, 0);
+        assert_eq!(world.get::<Health>(far).unwrap().current, 1);
+        // Back down through the rest of the step: 70 up to the brick, then 130 down
+        let transform = world.get::<Transform>(ball).unwrap();
+        assert_close(transform.translation.y, -60.);
+        assert_eq!(**world.get::<Velocity>(ball).unwrap(), -motion / STEP);
+    }
+
+    // Two walls with 40 between their inner faces, so a ball of radius 15 bounces every 40
+    fn corridor(world: &mut World) {
+        let size = Vec2::new(10., 200.);
+        spawn_wall(world, WallLocation::Left, Vec2::new(-40., 0.), size);
+        spawn_wall(world, WallLocation::Right, Vec2::new(40., 0.), size);
+    }
+
+    #[test]
+    fn ball_bounces_several_times_in_one_step() {
+        let mut world = collision_world();
+        corridor(&mut world);
+        let velocity = Vec2::new(62.5 / STEP, 0.);
+        let ball = spawn_ball(&mut world, Vec2::ZERO, velocity);
+
+        let events = run_collisions(&mut world);
+        let hits: Vec<_> = events.iter().map(|event| event.hit).collect();
+        assert_eq!(
+            hits,
+            [
+                Hit::Wall(WallLocation::Right),
+                Hit::Wall(WallLocation::Left)
+            ]
+        );
+        assert_eq!(events[0].velocity_before, velocity);
+        assert_eq!(events[0].velocity_after, -velocity);
+        assert_eq!(events[1].velocity_after, velocity);
+        // 20 to the right wall, 40 back to the left one, and the last 2.5 to the right
+        assert_close(world.get::<Transform>(ball).unwrap().translation.x, -17.5);
+    }
+
+    #[test]
+    fn bounces_past_the_limit_are_dropped() {
+        let mut world = collision_world();
+        corridor(&mut world);
+        let velocity = Vec2::new(250. / STEP, 0.);
+        let ball = spawn_ball(&mut world, Vec2::ZERO, velocity);
+
+        let events = run_collisions(&mut world);
+        assert_eq!(events.len(), MAX_BOUNCES_PER_STEP);
+        // The ball stops where it last bounced rather than carrying on through a wall
+        assert_close(world.get::<Transform>(ball).unwrap().translation.x, -20.);
+        assert_eq!(**world.get::<Velocity>(ball).unwrap(), velocity);
+    }
+
+    // A headless game on the first level with the served ball cleared away
+    fn arena_app() -> App {
+        let mut app = headless::test_app(0, None);
+        // Enter play, spawning the arena
+        app.update();
+        let world = app.world_mut();
+        let balls: Vec<Entity> = world
+            .query_filtered::<Entity, With<Ball>>()
+            .iter(world)
+            .collect();
+        for ball in balls {
+            world.despawn(ball);
+        }
+        app
+    }
+
+    // Spawn a ball at the current ball speed, heading in `direction`
+    fn launch_ball(app: &mut App, position: Vec2, direction: Vec2) -> Entity {
+        let world = app.world_mut();
+        let config = world.resource::<GameConfig>().clone();
+        let speed = world.resource::<BallSpeed>().current;
+        let ball = world.resource::<BallAssets>().ball(
+            position.extend(BALL_Z),
+            direction * speed,
+            &config,
+        );
+        world.spawn(ball).id()
+    }
+
+    // Run one fixed step, returning the collisions during it
+    fn step(app: &mut App) -> Vec<CollisionEvent> {
+        let mut cursor = app
+            .world()
+            .resource::<Events<CollisionEvent>>()
+            .get_cursor_current();
+        app.update();
+        let events = app.world().resource::<Events<CollisionEvent>>();
+        cursor.read(events).copied().collect()
+    }
+
+    fn velocity(app: &App, ball: Entity) -> Vec2 {
+        **app.world().get::<Velocity>(ball).unwrap()
+    }
+
+    // The breakable brick nearest the bottom of the arena, and where it is
+    fn lowest_brick(app: &mut App) -> (Entity, Vec2, usize) {
+        let world = app.world_mut();
+        world
+            .query_filtered::<(Entity, &Transform, &BrickRow), With<Health>>()
+            .iter(world)
+            .map(|(brick, transform, row)| (brick, transform.translation.truncate(), **row))
+            .min_by(|a, b| a.1.y.total_cmp(&b.1.y).then(a.1.x.total_cmp(&b.1.x)))
+            .expect("the level has breakable bricks")
+    }
+
+    #[test]
+    fn ball_bounces_off_a_wall_in_the_game() {
+        let mut app = arena_app();
+        let arena = app.world().resource::<GameConfig>().arena.clone();
+        // 3 away from the left wall's inner face
+        let x = arena.left + arena.wall_thickness / 2. + BALL_RADIUS + 3.;
+        let ball = launch_ball(&mut app, Vec2::new(x, 0.), Vec2::NEG_X);
+
+        let events = step(&mut app);
+        assert_eq!(events.len(), 1);
+        let event = events[0];
+        assert_eq!(event.ball, ball);
+        assert_eq!(event.hit, Hit::Wall(WallLocation::Left));
+        assert_eq!(event.side, Collision::Right);
+        assert_eq!(event.normal, Vec2::X);
+        assert_close(event.point.x, arena.left + arena.wall_thickness / 2.);
+        assert_direction(event.velocity_before, Vec2::NEG_X);
+        assert_eq!(event.velocity_after, -event.velocity_before);
+        assert_direction(velocity(&app, ball), Vec2::X);
+    }
+
+    #[test]
+    fn ball_breaks_a_brick_in_the_game() {
+        let mut app = arena_app();
+        let (brick, position, row) = lowest_brick(&mut app);
+        let brick_height = app.world().resource::<GameConfig>().bricks.size.y;
+        let start = position - Vec2::new(0., brick_height / 2. + BALL_RADIUS + 3.);
+        let ball = launch_ball(&mut app, start, Vec2::Y);
+        let score = **app.world().resource::<Score>();
+
+        let events = step(&mut app);
+        assert_eq!(events.len(), 1);
+        let event = events[0];
+        assert_eq!(event.collider, brick);
+        assert_eq!(
+            event.hit,
+            Hit::Brick {
+                kind: BrickKind::Standard,
+                row,
+                destroyed: true,
+            }
+        );
+        assert_eq!(event.side, Collision::Bottom);
+        assert_eq!(event.normal, Vec2::NEG_Y);
+        assert_eq!(event.velocity_after, -event.velocity_before);
+        assert_direction(velocity(&app, ball), Vec2::NEG_Y);
+        assert!(app.world().get_entity(brick).is_err());
+        assert!(**app.world().resource::<Score>() > score);
+    }
+
+    #[test]
+    fn ball_damages_a_reinforced_brick_in_the_game() {
+        let mut app = arena_app();
+        let (brick, position, row) = lowest_brick(&mut app);
+        app.world_mut().entity_mut(brick).insert(Health::new(3));
+        let brick_height = app.world().resource::<GameConfig>().bricks.size.y;
+        let start = position - Vec2::new(0., brick_height / 2. + BALL_RADIUS + 3.);
+        launch_ball(&mut app, start, Vec2::Y);
+
+        let events = step(&mut app);
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].hit,
+            Hit::Brick {
+                kind: BrickKind::Standard,
+                row,
+                destroyed: false,
+            }
+        );
+        let health = app.world().get::<Health>(brick).unwrap();
+        assert_eq!((health.current, health.max), (2, 3));
+    }
+
+    #[test]
+    fn ball_bounces_off_the_paddle_in_the_game() {
+        let mut app = arena_app();
+        let config = app.world().resource::<GameConfig>().clone();
+        let paddle_top = config.paddle_y() + config.paddle.size.y / 2.;
+        // Off center, so the ball goes back up at an angle
+        let ball = launch_ball(
+            &mut app,
+            Vec2::new(40., paddle_top + BALL_RADIUS + 3.),
+            Vec2::NEG_Y,
+        );
+
+        let events = step(&mut app);
+        assert_eq!(events.len(), 1);
+        let event = events[0];
+        assert_eq!(event.hit, Hit::Paddle);
+        assert_eq!(event.side, Collision::Top);
+        assert_close(event.point.y, paddle_top);
+
+        let world = app.world_mut();
+        let paddle = world
+            .query_filtered::<&Transform, With<Paddle>>()
+            .single(world);
+        let expected = paddle_bounce(
+            event.point.x - paddle.translation.x,
+            paddle.scale.x,
+            event.velocity_before.length(),
+            config.ball.max_bounce_angle,
+        );
+        assert!(expected.x > 0.);
+        assert_close(event.velocity_after.x, expected.x);
+        assert_close(event.velocity_after.y, expected.y);
+        assert_direction(velocity(&app, ball), expected);
+    }
+
+    #[test]
+    fn games_with_the_same_seed_play_out_the_same() {
+        let mut first = headless::test_app(7, None);
+        let mut second = headless::test_app(7, None);
+        headless::play(&mut first, 640);
+        headless::play(&mut second, 640);
+        let (first, second) = (first.world(), second.world());
+        assert_eq!(
+            first.resource::<replay::Recording>().checksum,
+            second.resource::<replay::Recording>().checksum
+        );
+        assert_eq!(**first.resource::<Score>(), **second.resource::<Score>());
+        assert_eq!(**first.resource::<Lives>(), **second.resource::<Lives>());
+        assert_eq!(
+            **first.resource::<level::CurrentLevel>(),
+            **second.resource::<level::CurrentLevel>()
+        );
+        assert_eq!(
+            first.resource::<State<GameState>>().get(),
+            second.resource::<State<GameState>>().get()
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Menus made of buttons, and the main menu and level select screens.
+//!
+//! A [`Menu`] is a node whose [`MenuItem`] children are chosen from with the
+//! arrow keys or the gamepad's d-pad, see [`RawInput::menu_input`], or with
+//! the mouse. Choosing an item triggers [`MenuPress`] on it and left and right
+//! trigger [`MenuAdjust`], while going back triggers [`MenuBack`] on the menu
+//! itself, so each screen says what its items do with observers:
+//!
+//! ```ignore
+//! commands.spawn(menu()).with_children(|p| {
+//!     spawn_menu_item(p, "Play").observe(|_: Trigger<MenuPress>| info!("play"));
+//! });
+//! ```
+
+use bevy::{ecs::system::EntityCommands, prelude::*};
+
+use crate::{
+    input::{MenuInput, RawInput},
+    level::{CampaignLevels, FirstLevel},
+    state::{screen_root, screen_text, screen_title, GameState},
+};
+
+const ITEM_WIDTH: f32 = 360.0;
+const ITEM_COLOR: Color = Color::srgb(0.8, 0.8, 0.85);
+const ITEM_SELECTED_COLOR: Color = Color::srgb(0.95, 0.7, 0.7);
+
+/// Plugin letting the player get around [`Menu`]s, and adding the main menu
+/// and level select screens
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::MainMenu), spawn_main_menu)
+            .add_systems(OnEnter(GameState::LevelSelect), spawn_level_select)
+            .add_systems(Update, (navigate_menus, highlight_menu_items).chain());
+    }
+}
+
+/// A column of [`MenuItem`]s, one of which is selected
+#[derive(Component, Debug, Default)]
+pub struct Menu {
+    /// Index of the selected item among the menu's items
+    selected: usize,
+}
+
+/// A button of a [`Menu`], see [`spawn_menu_item`]
+#[derive(Component, Debug)]
+pub struct MenuItem;
+
+/// Triggered on a [`MenuItem`] when it is pressed
+#[derive(Event, Debug)]
+pub struct MenuPress;
+
+/// Triggered on the selected [`MenuItem`] when left (-1) or right (+1) is pressed
+#[derive(Event, Debug, Deref)]
+pub struct MenuAdjust(pub i32);
+
+/// Triggered on a [`Menu`] when the player asks to go back
+#[derive(Event, Debug)]
+pub struct MenuBack;
+
+/// Node laying out a [`Menu`]'s items in a column
+pub fn menu() -> impl Bundle {
+    (
+        Node {
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(10.0),
+            ..default()
+        },
+        Menu::default(),
+    )
+}
+
+/// Add an item labelled `label` to the menu being built, returning it so that
+/// observers can be added to it
+pub fn spawn_menu_item<'a>(
+    parent: &'a mut ChildBuilder,
+    label: impl Into<String>,
+) -> EntityCommands<'a> {
+    let mut item = parent.spawn((
+        Button,
+        Node {
+            width: Val::Px(ITEM_WIDTH),
+            padding: UiRect::axes(Val::Px(20.0), Val::Px(8.0)),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        BackgroundColor(ITEM_COLOR),
+        MenuItem,
+    ));
+    item.with_child(screen_text(label));
+    item
+}
+
+/// Move around every menu on screen, and trigger the events of whatever the player picks
+fn navigate_menus(
+    mut commands: Commands,
+    input: RawInput,
+    mut menus: Query<(Entity, &mut Menu, &Children)>,
+    items: Query<(Entity, Ref<Interaction>), With<MenuItem>>,
+) {
+    let menu_input = input.menu_input();
+    for (entity, mut menu, children) in &mut menus {
+        let items: Vec<_> = items.iter_many(children).collect();
+        if items.is_empty() {
+            continue;
+        }
+        let count = items.len();
+        menu.selected = menu.selected.min(count - 1);
+
+        for (index, (item, interaction)) in items.iter().enumerate() {
+            if !interaction.is_changed() {
+                continue;
+            }
+            match **interaction {
+                Interaction::Hovered => menu.selected = index,
+                Interaction::Pressed => {
+                    menu.selected = index;
+                    commands.trigger_targets(MenuPress, *item);
+                }
+                Interaction::None => (),
+            }
+        }
+
+        let selected = items[menu.selected].0;
+        match menu_input {
+            Some(MenuInput::Up) => menu.selected = (menu.selected + count - 1) % count,
+            Some(MenuInput::Down) => menu.selected = (menu.selected + 1) % count,
+            Some(MenuInput::Left) => commands.trigger_targets(MenuAdjust(-1), selected),
+            Some(MenuInput::Right) => commands.trigger_targets(MenuAdjust(1), selected),
+            Some(MenuInput::Select) => commands.trigger_targets(MenuPress, selected),
+            Some(MenuInput::Back) => commands.trigger_targets(MenuBack, entity),
+            None => (),
+        }
+    }
+}
+
+fn highlight_menu_items(
+    menus: Query<(&Menu, &Children)>,
+    mut items: Query<&mut BackgroundColor, With<MenuItem>>,
+) {
+    for (menu, children) in &menus {
+        let mut items = items.iter_many_mut(children);
+        let mut index = 0;
+        while let Some(mut color) = items.fetch_next() {
+            color.set_if_neq(BackgroundColor(if index == menu.selected {
+                ITEM_SELECTED_COLOR
+            } else {
+                ITEM_COLOR
+            }));
+            index += 1;
+        }
+    }
+}
+
+/// Returns an observer moving on to `state`
+fn go_to<E: Event>(state: GameState) -> impl Fn(Trigger<E>, ResMut<NextState<GameState>>) {
+    move |_, mut next_state| next_state.set(state)
+}
+
+fn spawn_main_menu(mut commands: Commands) {
+    commands
+        .spawn(screen_root(GameState::MainMenu))
+        .with_children(|p| {
+            p.spawn(screen_title("Breakout"));
+            p.spawn(menu()).with_children(|p| {
+                spawn_menu_item(p, "Play").observe(go_to::<MenuPress>(GameState::Playing));
+                spawn_menu_item(p, "Level select")
+                    .observe(go_to::<MenuPress>(GameState::LevelSelect));
+                spawn_menu_item(p, "High scores")
+                    .observe(go_to::<MenuPress>(GameState::HighScores));
+                spawn_menu_item(p, "Settings").observe(go_to::<MenuPress>(GameState::Settings));
+                spawn_menu_item(p, "Quit").observe(
+                    |_: Trigger<MenuPress>, mut app_exit_events: EventWriter<AppExit>| {
+                        app_exit_events.send(AppExit::Success);
+                    },
+                );
+            });
+            p.spawn(screen_text("Up/Down: choose, Enter: select"));
+        });
+}
+
+fn spawn_level_select(mut commands: Commands, levels: CampaignLevels) {
+    commands
+        .spawn(screen_root(GameState::LevelSelect))
+        .with_children(|p| {
+            p.spawn(screen_title("Level select"));
+            p.spawn(menu())
+                .observe(go_to::<MenuBack>(GameState::MainMenu))
+                .with_children(|p| {
+                    for index in 0..levels.count() {
+                        let Some(level) = levels.get(index) else {
+                            continue;
+                        };
+                        spawn_menu_item(p, format!("{}. {}", index + 1, level.name)).observe(
+                            move |_: Trigger<MenuPress>,
+                                  mut first_level: ResMut<FirstLevel>,
+                                  mut next_state: ResMut<NextState<GameState>>| {
+                                **first_level = index;
+                                next_state.set(GameState::Playing);
+                            },
+                        );
+                    }
+                });
+            p.spawn(screen_text("Up/Down: choose, Enter: play, Esc: back"));
+        });
+}
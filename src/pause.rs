@@ -0,0 +1,101 @@
+//! The pause menu.
+//!
+//! Pausing freezes [`Time<Virtual>`], which stops `FixedUpdate` and everything
+//! else that runs on game time until the game is resumed. The menu can resume
+//! the game, restart the current level, open the settings screen or give up
+//! and go back to the main menu. The game also pauses by itself whenever its
+//! window loses focus.
+
+use bevy::{prelude::*, window::WindowFocused};
+
+use crate::{
+    input::{Action, Actions, InputBindings},
+    level::RestartLevel,
+    menu::{menu, spawn_menu_item, MenuBack, MenuPress},
+    state::{screen_root, screen_text, screen_title, GameState, PauseMenu},
+};
+
+/// Plugin adding the pause menu, and pausing when the window loses focus
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Paused), pause_time)
+            .add_systems(OnExit(GameState::Paused), resume_time)
+            .add_systems(OnEnter(PauseMenu::Main), spawn_pause_menu)
+            .add_systems(
+                Update,
+                pause_on_focus_loss.run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Update,
+                handle_pause_actions.run_if(in_state(PauseMenu::Main)),
+            );
+    }
+}
+
+fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn resume_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+fn pause_on_focus_loss(
+    mut focus_events: EventReader<WindowFocused>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if focus_events.read().any(|event| !event.focused) {
+        next_state.set(GameState::Paused);
+    }
+}
+
+fn resume<E: Event>(_: Trigger<E>, mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Playing);
+}
+
+fn spawn_pause_menu(mut commands: Commands, bindings: Res<InputBindings>) {
+    commands
+        .spawn(screen_root(PauseMenu::Main))
+        .with_children(|p| {
+            p.spawn(screen_title("Paused"));
+            p.spawn(menu())
+                .observe(resume::<MenuBack>)
+                .with_children(|p| {
+                    spawn_menu_item(p, "Resume").observe(resume::<MenuPress>);
+                    spawn_menu_item(p, "Restart level").observe(
+                        |_: Trigger<MenuPress>,
+                         mut commands: Commands,
+                         mut next_state: ResMut<NextState<GameState>>| {
+                            commands.trigger(RestartLevel);
+                            next_state.set(GameState::Playing);
+                        },
+                    );
+                    spawn_menu_item(p, "Settings").observe(
+                        |_: Trigger<MenuPress>, mut next_menu: ResMut<NextState<PauseMenu>>| {
+                            next_menu.set(PauseMenu::Settings);
+                        },
+                    );
+                    spawn_menu_item(p, "Quit to menu").observe(
+                        |_: Trigger<MenuPress>, mut next_state: ResMut<NextState<GameState>>| {
+                            next_state.set(GameState::MainMenu);
+                        },
+                    );
+                });
+            p.spawn(screen_text(format!(
+                "{}: resume, {}: quit to the menu",
+                bindings.describe(Action::Pause),
+                bindings.describe(Action::QuitToMenu)
+            )));
+        });
+}
+
+/// The actions stay bound even while the menu is open
+fn handle_pause_actions(actions: Res<Actions>, mut next_state: ResMut<NextState<GameState>>) {
+    if actions.just_pressed(Action::Pause) {
+        next_state.set(GameState::Playing);
+    } else if actions.just_pressed(Action::QuitToMenu) {
+        next_state.set(GameState::MainMenu);
+    }
+}
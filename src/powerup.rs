@@ -0,0 +1,406 @@
+//! Power-ups dropped by broken bricks.
+//!
+//! Every broken brick has a chance to drop a capsule that falls towards the
+//! floor. Catching it with the paddle triggers its effect: some happen at once,
+//! others last for a while and are tracked in [`ActivePowerUps`].
+
+use std::{collections::HashMap, time::Duration};
+
+use bevy::{
+    math::bounding::{Aabb2d, IntersectsVolume},
+    prelude::*,
+};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    config::GameConfig,
+    difficulty::{update_ball_speed, BallSpeed},
+    input::PaddleInput,
+    level::StartLevel,
+    rng::GameRng,
+    state::{simulating, InGame},
+    Ball, BallAssets, BrickDestroyed, Collider, Health, LifeLost, Lives, Paddle, StuckToPaddle,
+    Velocity,
+};
+
+// Chance for a broken brick to drop a power-up
+const POWER_UP_DROP_CHANCE: f64 = 0.15;
+const POWER_UP_SIZE: Vec2 = Vec2::new(60.0, 20.0);
+const POWER_UP_FALL_SPEED: f32 = 150.0;
+const POWER_UP_FONT_SIZE: f32 = 16.0;
+const POWER_UP_TEXT_COLOR: Color = Color::srgb(1.0, 1.0, 1.0);
+// How long timed power-ups last once caught
+const POWER_UP_DURATION: Duration = Duration::from_secs(10);
+
+const WIDE_PADDLE_FACTOR: f32 = 1.5;
+const SLOW_BALL_FACTOR: f32 = 0.6;
+// Angle between the ball that caught a multi-ball and each of the new balls
+const MULTI_BALL_SPREAD: f32 = 25.0 * std::f32::consts::PI / 180.0;
+
+const LASER_SIZE: Vec2 = Vec2::new(4.0, 16.0);
+const LASER_SPEED: f32 = 600.0;
+const LASER_COOLDOWN: Duration = Duration::from_millis(400);
+const LASER_COLOR: Color = Color::srgb(0.9, 0.2, 0.2);
+
+/// Plugin adding power-up drops and their effects
+pub struct PowerUpPlugin;
+
+impl Plugin for PowerUpPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActivePowerUps>()
+            .add_systems(OnEnter(InGame), clear_power_ups)
+            .add_systems(StartLevel, clear_power_ups)
+            .add_systems(
+                FixedUpdate,
+                (
+                    (
+                        tick_power_ups,
+                        update_paddle_effects,
+                        apply_ball_speed.after(update_ball_speed),
+                        // Only the paddle has a cannon, and only while the power-up lasts
+                        fire_lasers.run_if(any_with_component::<LaserCannon>),
+                    )
+                        .chain()
+                        .after(PaddleInput)
+                        .before(crate::move_paddle),
+                    check_laser_hits
+                        .after(crate::check_for_collisions)
+                        .before(crate::break_bricks),
+                    (
+                        drop_power_ups,
+                        catch_power_ups,
+                        clear_power_ups.run_if(on_event::<LifeLost>),
+                    )
+                        .chain()
+                        // An extra life caught as the last ball is lost counts after the loss,
+                        // and power-ups are cleared in the same step the life is lost
+                        .after(crate::check_for_ball_lost),
+                )
+                    .run_if(simulating),
+            );
+    }
+}
+
+/// The effects a power-up can have when caught
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerUpKind {
+    /// Makes the paddle wider for a while
+    WidePaddle,
+    /// Splits the ball into three
+    MultiBall,
+    /// Slows every ball down for a while
+    SlowBall,
+    /// Balls stick to the paddle for a while, until launched
+    StickyPaddle,
+    /// The paddle shoots at the bricks for a while
+    Laser,
+    /// One more life
+    ExtraLife,
+}
+
+impl PowerUpKind {
+    const ALL: [PowerUpKind; 6] = [
+        PowerUpKind::WidePaddle,
+        PowerUpKind::MultiBall,
+        PowerUpKind::SlowBall,
+        PowerUpKind::StickyPaddle,
+        PowerUpKind::Laser,
+        PowerUpKind::ExtraLife,
+    ];
+
+    /// How likely this kind is to be picked when a brick drops a power-up
+    fn weight(&self) -> u32 {
+        match self {
+            PowerUpKind::WidePaddle | PowerUpKind::SlowBall => 4,
+            PowerUpKind::MultiBall | PowerUpKind::StickyPaddle => 3,
+            PowerUpKind::Laser => 2,
+            PowerUpKind::ExtraLife => 1,
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            PowerUpKind::WidePaddle => Color::srgb(0.3, 0.6, 0.9),
+            PowerUpKind::MultiBall => Color::srgb(0.9, 0.5, 0.2),
+            PowerUpKind::SlowBall => Color::srgb(0.3, 0.7, 0.4),
+            PowerUpKind::StickyPaddle => Color::srgb(0.7, 0.4, 0.8),
+            PowerUpKind::Laser => LASER_COLOR,
+            PowerUpKind::ExtraLife => Color::srgb(0.9, 0.4, 0.6),
+        }
+    }
+
+    /// Letter printed on the capsule
+    fn label(&self) -> &'static str {
+        match self {
+            PowerUpKind::WidePaddle => "W",
+            PowerUpKind::MultiBall => "M",
+            PowerUpKind::SlowBall => "S",
+            PowerUpKind::StickyPaddle => "C",
+            PowerUpKind::Laser => "L",
+            PowerUpKind::ExtraLife => "+1",
+        }
+    }
+}
+
+/// A falling capsule waiting to be caught
+#[derive(Component, Deref)]
+struct PowerUp(PowerUpKind);
+
+/// Timed effects currently in play, along with how long they have left
+#[derive(Resource, Default)]
+pub struct ActivePowerUps(HashMap<PowerUpKind, Timer>);
+
+impl ActivePowerUps {
+    pub fn is_active(&self, kind: PowerUpKind) -> bool {
+        self.0.contains_key(&kind)
+    }
+
+    /// Start a timed effect, or restart its timer if it is already running
+    fn activate(&mut self, kind: PowerUpKind, duration: Duration) {
+        self.0.insert(kind, Timer::new(duration, TimerMode::Once));
+    }
+}
+
+/// Balls hitting the top of a paddle with this component stick to it
+#[derive(Component)]
+pub struct Sticky;
+
+/// The paddle shoots a pair of laser bolts whenever the timer finishes
+#[derive(Component, Deref, DerefMut)]
+struct LaserCannon(Timer);
+
+#[derive(Component)]
+struct LaserBolt;
+
+/// Give each broken brick a chance to drop a power-up where it was
+fn drop_power_ups(
+    mut commands: Commands,
+    mut brick_destroyed_events: EventReader<BrickDestroyed>,
+    mut rng: ResMut<GameRng>,
+) {
+    for event in brick_destroyed_events.read() {
+        if !rng.gen_bool(POWER_UP_DROP_CHANCE) {
+            continue;
+        }
+        let Ok(kind) = PowerUpKind::ALL.choose_weighted(&mut **rng, PowerUpKind::weight) else {
+            continue;
+        };
+
+        commands
+            .spawn((
+                Sprite::from_color(kind.color(), POWER_UP_SIZE),
+                Transform::from_translation(event.position.extend(0.5)),
+                PowerUp(*kind),
+                Velocity(Vec2::NEG_Y * POWER_UP_FALL_SPEED),
+                StateScoped(InGame),
+            ))
+            .with_child((
+                Text2d::new(kind.label()),
+                TextFont {
+                    font_size: POWER_UP_FONT_SIZE,
+                    ..default()
+                },
+                TextColor(POWER_UP_TEXT_COLOR),
+                // Just in front of the capsule
+                Transform::from_xyz(0.0, 0.0, 0.1),
+            ));
+    }
+}
+
+/// Apply the power-ups the paddle touches and throw away the ones that fell past it
+#[allow(clippy::too_many_arguments)]
+fn catch_power_ups(
+    mut commands: Commands,
+    power_ups: Query<(Entity, &Transform, &PowerUp)>,
+    paddle_transform: Single<&Transform, With<Paddle>>,
+    balls: Query<(&Transform, &Velocity), With<Ball>>,
+    ball_assets: Res<BallAssets>,
+    config: Res<GameConfig>,
+    mut active: ResMut<ActivePowerUps>,
+    mut lives: ResMut<Lives>,
+) {
+    let paddle = Aabb2d::new(
+        paddle_transform.translation.truncate(),
+        paddle_transform.scale.truncate() / 2.,
+    );
+
+    for (entity, transform, power_up) in &power_ups {
+        let capsule = Aabb2d::new(transform.translation.truncate(), POWER_UP_SIZE / 2.);
+        if !capsule.intersects(&paddle) {
+            if capsule.max.y < config.arena.bottom {
+                commands.entity(entity).despawn_recursive();
+            }
+            continue;
+        }
+        commands.entity(entity).despawn_recursive();
+
+        let kind = **power_up;
+        match kind {
+            PowerUpKind::WidePaddle
+            | PowerUpKind::SlowBall
+            | PowerUpKind::StickyPaddle
+            | PowerUpKind::Laser => active.activate(kind, POWER_UP_DURATION),
+            // The last ball was lost earlier in this step, and the game with it
+            PowerUpKind::ExtraLife if **lives == 0 => (),
+            PowerUpKind::ExtraLife => **lives += 1,
+            PowerUpKind::MultiBall => {
+                // Split off two new balls from one that's already in play
+                let Some((ball_transform, ball_velocity)) =
+                    balls.iter().min_by(|(a, _), (b, _)| {
+                        crate::spatial_order(a.translation.truncate(), b.translation.truncate())
+                    })
+                else {
+                    continue;
+                };
+                let velocity = if **ball_velocity == Vec2::ZERO {
+                    Vec2::Y * config.ball.speed
+                } else {
+                    **ball_velocity
+                };
+                for angle in [-MULTI_BALL_SPREAD, MULTI_BALL_SPREAD] {
+                    commands.spawn(ball_assets.ball(
+                        ball_transform.translation,
+                        Vec2::from_angle(angle).rotate(velocity),
+                        &config,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Count down the timed power-ups and drop the ones that ran out
+fn tick_power_ups(mut active: ResMut<ActivePowerUps>, time: Res<Time>) {
+    active
+        .0
+        .retain(|_, timer| !timer.tick(time.delta()).finished());
+}
+
+/// Keep the paddle's size and abilities in line with the active power-ups
+fn update_paddle_effects(
+    mut commands: Commands,
+    paddle: Single<(Entity, &mut Transform, Has<Sticky>, Has<LaserCannon>), With<Paddle>>,
+    active: Res<ActivePowerUps>,
+    config: Res<GameConfig>,
+) {
+    let (entity, mut transform, is_sticky, has_laser) = paddle.into_inner();
+
+    transform.scale.x = if active.is_active(PowerUpKind::WidePaddle) {
+        config.paddle.size.x * WIDE_PADDLE_FACTOR
+    } else {
+        config.paddle.size.x
+    };
+
+    match (active.is_active(PowerUpKind::StickyPaddle), is_sticky) {
+        (true, false) => {
+            commands.entity(entity).insert(Sticky);
+        }
+        (false, true) => {
+            commands.entity(entity).remove::<Sticky>();
+        }
+        _ => (),
+    }
+
+    match (active.is_active(PowerUpKind::Laser), has_laser) {
+        (true, false) => {
+            commands.entity(entity).insert(LaserCannon(Timer::new(
+                LASER_COOLDOWN,
+                TimerMode::Repeating,
+            )));
+        }
+        (false, true) => {
+            commands.entity(entity).remove::<LaserCannon>();
+        }
+        _ => (),
+    }
+}
+
+/// Keep every moving ball at the speed the active power-ups call for, without changing its direction
+fn apply_ball_speed(
+    mut balls: Query<&mut Velocity, (With<Ball>, Without<StuckToPaddle>)>,
+    active: Res<ActivePowerUps>,
+    ball_speed: Res<BallSpeed>,
+) {
+    let speed = if active.is_active(PowerUpKind::SlowBall) {
+        ball_speed.current * SLOW_BALL_FACTOR
+    } else {
+        ball_speed.current
+    };
+    for mut velocity in &mut balls {
+        **velocity = velocity.normalize_or_zero() * speed;
+    }
+}
+
+/// Shoot a bolt from each end of a paddle that has a laser
+fn fire_lasers(
+    mut commands: Commands,
+    paddle: Single<(&Transform, &mut LaserCannon), With<Paddle>>,
+    time: Res<Time>,
+) {
+    let (transform, mut cannon) = paddle.into_inner();
+    if !cannon.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let half_size = transform.scale.truncate() / 2.;
+    for side in [-1.0, 1.0] {
+        let position = transform.translation.truncate()
+            + Vec2::new(
+                side * (half_size.x - LASER_SIZE.x),
+                half_size.y + LASER_SIZE.y / 2.,
+            );
+        commands.spawn((
+            Sprite::from_color(LASER_COLOR, LASER_SIZE),
+            Transform::from_translation(position.extend(0.5)),
+            LaserBolt,
+            Velocity(Vec2::Y * LASER_SPEED),
+            StateScoped(InGame),
+        ));
+    }
+}
+
+/// Laser bolts take a hit point off the first brick they touch, and stop at anything else
+fn check_laser_hits(
+    mut commands: Commands,
+    bolts: Query<(Entity, &Transform), With<LaserBolt>>,
+    mut colliders: Query<(&Transform, Option<&mut Health>), (With<Collider>, Without<Paddle>)>,
+) {
+    for (bolt_entity, bolt_transform) in &bolts {
+        let bolt = Aabb2d::new(bolt_transform.translation.truncate(), LASER_SIZE / 2.);
+        for (collider_transform, maybe_health) in &mut colliders {
+            // Bricks broken this step are waiting to be despawned
+            if maybe_health
+                .as_ref()
+                .is_some_and(|health| health.current == 0)
+            {
+                continue;
+            }
+            let collider = Aabb2d::new(
+                collider_transform.translation.truncate(),
+                collider_transform.scale.truncate() / 2.,
+            );
+            if !bolt.intersects(&collider) {
+                continue;
+            }
+
+            if let Some(mut health) = maybe_health {
+                health.current -= 1;
+            }
+            commands.entity(bolt_entity).despawn();
+            break;
+        }
+    }
+}
+
+/// Remove every power-up, whether still falling or already active.
+/// Balls that are stuck to the paddle stay there until they are launched.
+fn clear_power_ups(
+    mut commands: Commands,
+    drops: Query<Entity, Or<(With<PowerUp>, With<LaserBolt>)>>,
+    mut active: ResMut<ActivePowerUps>,
+) {
+    for entity in &drops {
+        commands.entity(entity).despawn_recursive();
+    }
+    active.0.clear();
+}
@@ -0,0 +1,489 @@
+//! Recording games so that they can be played back exactly.
+//!
+//! Every game is recorded as it is played: the RNG seed, the config, the
+//! difficulty, the level it started at, the [`PaddleCommand`] of every fixed
+//! step and when the level was restarted, along with a checksum of the balls
+//! and bricks after the last step. `--record <file>` saves the latest game as
+//! RON when it ends or when the game is closed.
+//!
+//! `--replay <file>` starts from the recorded seed, config, difficulty and
+//! level and feeds the recorded commands and restarts back instead of the
+//! player's, then checks that the game ended up with the same checksum.
+//! Together with `--headless` this needs no input at all. A recording only
+//! holds as long as the level files stay the same and the config isn't changed
+//! on disk during the game.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    config::GameConfig,
+    difficulty::Difficulty,
+    input::{read_actions, PaddleCommand, PaddleInput},
+    level::{FirstLevel, RestartLevel},
+    rng::RngSeed,
+    spatial_order,
+    state::{simulating, InGame},
+    Ball, Brick, Health, Lives, Score, Velocity,
+};
+
+/// Current version of the replay file format
+const FILE_VERSION: u32 = 1;
+
+/// Plugin recording every game, and playing back a recorded one if asked to
+pub struct ReplayPlugin {
+    /// Where to save the latest game, if anywhere
+    pub record: Option<PathBuf>,
+    /// Recording to feed back instead of the player's input
+    pub playback: Option<Replay>,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Recording>()
+            .insert_resource(RecordPath(self.record.clone()))
+            .add_systems(OnEnter(InGame), start_recording)
+            .add_systems(OnExit(InGame), save_recording)
+            .add_systems(Last, save_recording.run_if(on_event::<AppExit>))
+            .add_systems(FixedPostUpdate, record_step.run_if(simulating))
+            .add_observer(record_restart);
+
+        if let Some(replay) = &self.playback {
+            app.insert_resource(Playback {
+                commands: replay.commands().collect(),
+                replay: replay.clone(),
+                position: 0,
+                restarts: 0,
+            })
+            // Restarts happen between steps, so they are played back before the next one starts
+            .add_systems(FixedFirst, play_back_restarts)
+            .add_systems(
+                FixedUpdate,
+                play_back
+                    .in_set(PaddleInput)
+                    .after(read_actions)
+                    .run_if(simulating),
+            )
+            .add_systems(
+                FixedPostUpdate,
+                check_playback.after(record_step).run_if(simulating),
+            );
+        }
+    }
+}
+
+/// A recorded game, as saved on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    version: u32,
+    /// Seed the game's randomness started from
+    pub seed: u64,
+    /// Config the game was played with
+    pub config: GameConfig,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    /// Index into the campaign of the level the game started at
+    #[serde(default)]
+    pub first_level: usize,
+    /// Number of fixed steps recorded
+    pub steps: u32,
+    /// Commands of every step, as runs of identical commands
+    commands: Vec<(u32, PaddleCommand)>,
+    /// Number of steps played before each time the level was restarted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    restarts: Vec<u32>,
+    /// [`checksum`] after the last step
+    pub checksum: u64,
+}
+
+impl Replay {
+    /// Read a replay file, checking that its version is one we know
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let contents = fs::read_to_string(path)?;
+        match ron::from_str::<FileVersion>(&contents)?.version {
+            FILE_VERSION => Ok(ron::from_str(&contents)?),
+            version => Err(ReplayError::UnknownVersion(version)),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// The command of every recorded step, in order
+    fn commands(&self) -> impl Iterator<Item = PaddleCommand> + '_ {
+        self.commands
+            .iter()
+            .flat_map(|&(count, command)| std::iter::repeat_n(command, count as usize))
+    }
+}
+
+/// Just the version of a replay file, read before the rest of it
+#[derive(Deserialize)]
+struct FileVersion {
+    version: u32,
+}
+
+/// Possible errors while reading or writing a replay file
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("could not access the replay file: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not parse the replay file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not write the replay file: {0}")]
+    Write(#[from] ron::Error),
+    #[error("the replay file has version {0}, but only version {FILE_VERSION} is known")]
+    UnknownVersion(u32),
+}
+
+/// The game being played, recorded step by step
+#[derive(Resource, Deref)]
+pub struct Recording(Replay);
+
+impl Default for Recording {
+    fn default() -> Self {
+        Recording(Replay {
+            version: FILE_VERSION,
+            seed: 0,
+            config: GameConfig::default(),
+            difficulty: Difficulty::default(),
+            first_level: 0,
+            steps: 0,
+            commands: Vec::new(),
+            restarts: Vec::new(),
+            checksum: 0,
+        })
+    }
+}
+
+/// Where to save the latest game, from `--record`
+#[derive(Resource, Deref)]
+pub struct RecordPath(Option<PathBuf>);
+
+/// A recording being fed back, and how far along it is
+#[derive(Resource)]
+pub struct Playback {
+    pub replay: Replay,
+    /// [`Replay::commands`], spelled out step by step
+    commands: Vec<PaddleCommand>,
+    position: usize,
+    /// How many of the recorded restarts have been played back
+    restarts: usize,
+}
+
+impl Playback {
+    /// Whether every recorded step has been played back
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.commands.len()
+    }
+}
+
+fn start_recording(
+    mut recording: ResMut<Recording>,
+    seed: Res<RngSeed>,
+    config: Res<GameConfig>,
+    difficulty: Res<Difficulty>,
+    first_level: Res<FirstLevel>,
+    playback: Option<ResMut<Playback>>,
+) {
+    *recording = Recording(Replay {
+        seed: **seed,
+        config: config.clone(),
+        difficulty: *difficulty,
+        first_level: **first_level,
+        ..Recording::default().0
+    });
+    if let Some(mut playback) = playback {
+        playback.position = 0;
+        playback.restarts = 0;
+    }
+}
+
+/// Add the step that just ran to the recording
+fn record_step(
+    mut recording: ResMut<Recording>,
+    command: Res<PaddleCommand>,
+    balls: Query<(&Transform, &Velocity), With<Ball>>,
+    bricks: Query<(&Transform, Option<&Health>), With<Brick>>,
+    score: Res<Score>,
+    lives: Res<Lives>,
+) {
+    let replay = &mut recording.0;
+    match replay.commands.last_mut() {
+        Some((count, last)) if *last == *command => *count += 1,
+        _ => replay.commands.push((1, *command)),
+    }
+    replay.steps += 1;
+    replay.checksum = checksum(&balls, &bricks, **score, **lives);
+}
+
+fn record_restart(_: Trigger<RestartLevel>, mut recording: ResMut<Recording>) {
+    let steps = recording.steps;
+    recording.0.restarts.push(steps);
+}
+
+/// Save the latest game, if it was asked for on the command line
+pub fn save_recording(recording: Res<Recording>, path: Res<RecordPath>) {
+    let Some(path) = &**path else {
+        return;
+    };
+    if recording.steps == 0 {
+        return;
+    }
+    match recording.save(path) {
+        Ok(()) => info!(
+            "saved a replay of {} steps to {}",
+            recording.steps,
+            path.display()
+        ),
+        Err(error) => error!("{error}"),
+    }
+}
+
+/// Replace the player's command with the recorded one, for as long as the recording lasts
+fn play_back(mut playback: ResMut<Playback>, mut command: ResMut<PaddleCommand>) {
+    let Some(&recorded) = playback.commands.get(playback.position) else {
+        return;
+    };
+    *command = recorded;
+    playback.position += 1;
+}
+
+/// Restart the level wherever it was restarted in the recording
+fn play_back_restarts(
+    mut commands: Commands,
+    mut playback: ResMut<Playback>,
+    recording: Res<Recording>,
+) {
+    while playback.replay.restarts.get(playback.restarts) == Some(&recording.steps) {
+        commands.trigger(RestartLevel);
+        playback.restarts += 1;
+    }
+}
+
+/// Report whether the game ended up where the recording did
+fn check_playback(playback: Res<Playback>, recording: Res<Recording>, mut reported: Local<bool>) {
+    if !playback.is_finished() || *reported {
+        return;
+    }
+    *reported = true;
+    if recording.checksum == playback.replay.checksum {
+        info!(
+            "replay finished after {} steps, matching the recording",
+            recording.steps
+        );
+    } else {
+        warn!(
+            "replay finished after {} steps with checksum {:016x}, but the recording has {:016x}",
+            recording.steps, recording.checksum, playback.replay.checksum
+        );
+    }
+}
+
+/// Hash of everything that decides how the game goes on: the balls, the bricks, the score and lives.
+///
+/// Entities are hashed in [`spatial_order`] rather than the order queries return them in.
+pub fn checksum(
+    balls: &Query<(&Transform, &Velocity), With<Ball>>,
+    bricks: &Query<(&Transform, Option<&Health>), With<Brick>>,
+    score: usize,
+    lives: usize,
+) -> u64 {
+    let mut ball_states: Vec<(Vec2, Vec2)> = balls
+        .iter()
+        .map(|(transform, velocity)| (transform.translation.truncate(), **velocity))
+        .collect();
+    ball_states.sort_by(|a, b| spatial_order(a.0, b.0).then(spatial_order(a.1, b.1)));
+
+    let mut brick_states: Vec<(Vec2, u32)> = bricks
+        .iter()
+        .map(|(transform, health)| {
+            // Unbreakable bricks have no health to hash
+            let health = health.map_or(u32::MAX, |health| health.current);
+            (transform.translation.truncate(), health)
+        })
+        .collect();
+    brick_states.sort_by(|a, b| spatial_order(a.0, b.0));
+
+    // FNV-1a, which unlike the standard library's hasher is guaranteed to stay the same
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut write = |bytes: [u8; 8]| {
+        for byte in bytes {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for (position, velocity) in ball_states {
+        for value in [position.x, position.y, velocity.x, velocity.y] {
+            write(f64::from(value).to_le_bytes());
+        }
+    }
+    for (position, health) in brick_states {
+        write(f64::from(position.x).to_le_bytes());
+        write(f64::from(position.y).to_le_bytes());
+        write(u64::from(health).to_le_bytes());
+    }
+    write((score as u64).to_le_bytes());
+    write((lives as u64).to_le_bytes());
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::headless::{play, test_app};
+
+    fn command(direction: f32, launch: bool) -> PaddleCommand {
+        PaddleCommand {
+            direction,
+            launch,
+            target: None,
+        }
+    }
+
+    #[test]
+    fn commands_round_trip_through_runs() {
+        let steps = [
+            command(0.0, true),
+            command(1.0, false),
+            command(1.0, false),
+            command(1.0, false),
+            command(-1.0, false),
+            PaddleCommand {
+                target: Some(12.5),
+                ..command(1.0, false)
+            },
+            command(1.0, false),
+            command(1.0, false),
+        ];
+
+        let mut world = World::new();
+        world.init_resource::<Recording>();
+        world.insert_resource(Score(0));
+        world.insert_resource(Lives(3));
+        for step in steps {
+            world.insert_resource(step);
+            world.run_system_once(record_step).unwrap();
+        }
+
+        let recording = world.resource::<Recording>();
+        assert_eq!(recording.steps, steps.len() as u32);
+        assert_eq!(
+            recording.commands,
+            [
+                (1, steps[0]),
+                (3, steps[1]),
+                (1, steps[4]),
+                (1, steps[5]),
+                (2, steps[6]),
+            ]
+        );
+        assert!(recording.commands().eq(steps));
+
+        // And through the file format
+        let contents = ron::to_string(&recording.0).unwrap();
+        let loaded: Replay = ron::from_str(&contents).unwrap();
+        assert!(loaded.commands().eq(steps));
+    }
+
+    fn spawn_ball(world: &mut World, position: Vec2) -> Entity {
+        world
+            .spawn((
+                Ball,
+                Velocity(Vec2::new(100.0, 200.0)),
+                Transform::from_translation(position.extend(1.0)),
+            ))
+            .id()
+    }
+
+    fn spawn_brick(world: &mut World, position: Vec2, health: Option<Health>) -> Entity {
+        let brick = world
+            .spawn((Brick, Transform::from_translation(position.extend(0.0))))
+            .id();
+        if let Some(health) = health {
+            world.entity_mut(brick).insert(health);
+        }
+        brick
+    }
+
+    fn world_checksum(world: &mut World) -> u64 {
+        world
+            .run_system_once(
+                |balls: Query<(&Transform, &Velocity), With<Ball>>,
+                 bricks: Query<(&Transform, Option<&Health>), With<Brick>>| {
+                    checksum(&balls, &bricks, 10, 3)
+                },
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn checksum_follows_bricks_and_balls() {
+        let mut world = World::new();
+        let ball = spawn_ball(&mut world, Vec2::new(0.0, -100.0));
+        let brick = spawn_brick(&mut world, Vec2::new(-50.0, 100.0), Some(Health::new(3)));
+        spawn_brick(&mut world, Vec2::new(50.0, 100.0), Some(Health::new(1)));
+        spawn_brick(&mut world, Vec2::new(0.0, 150.0), None);
+        let original = world_checksum(&mut world);
+        assert_eq!(world_checksum(&mut world), original);
+
+        world.get_mut::<Health>(brick).unwrap().current = 2;
+        let damaged = world_checksum(&mut world);
+        assert_ne!(damaged, original);
+        world.get_mut::<Health>(brick).unwrap().current = 3;
+        assert_eq!(world_checksum(&mut world), original);
+
+        world.get_mut::<Transform>(ball).unwrap().translation.x += 0.5;
+        assert_ne!(world_checksum(&mut world), original);
+        world.get_mut::<Transform>(ball).unwrap().translation.x -= 0.5;
+        assert_eq!(world_checksum(&mut world), original);
+    }
+
+    #[test]
+    fn checksum_ignores_spawn_order() {
+        let positions = [Vec2::new(-50.0, 100.0), Vec2::new(50.0, 100.0)];
+        let mut forwards = World::new();
+        let mut backwards = World::new();
+        for position in positions {
+            spawn_brick(&mut forwards, position, Some(Health::new(1)));
+        }
+        for position in positions.into_iter().rev() {
+            spawn_brick(&mut backwards, position, Some(Health::new(1)));
+        }
+        assert_eq!(
+            world_checksum(&mut forwards),
+            world_checksum(&mut backwards)
+        );
+    }
+
+    #[test]
+    fn replay_with_a_restart_ends_where_the_recording_did() {
+        let mut app = test_app(7, None);
+        play(&mut app, 300);
+        app.world_mut().trigger(RestartLevel);
+        play(&mut app, 600);
+        let recorded = app.world().resource::<Recording>().0.clone();
+        assert_eq!(recorded.steps, 600, "the game should still be going");
+        assert_eq!(recorded.restarts, [300]);
+
+        let mut app = test_app(0, Some(recorded.clone()));
+        play(&mut app, recorded.steps);
+        let world = app.world();
+        assert!(world.resource::<Playback>().is_finished());
+        let replayed = world.resource::<Recording>();
+        assert_eq!(replayed.steps, recorded.steps);
+        assert_eq!(replayed.restarts, recorded.restarts);
+        assert_eq!(replayed.checksum, recorded.checksum);
+    }
+}
@@ -0,0 +1,39 @@
+//! Seedable random numbers, so that a run can be reproduced from its seed.
+
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::state::InGame;
+
+/// Plugin providing the [`GameRng`] resource
+pub struct RngPlugin {
+    /// Seed for every game played in this session, or `None` to pick one at random
+    pub seed: Option<u64>,
+}
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = RngSeed(self.seed.unwrap_or_else(rand::random));
+        app.insert_resource(seed)
+            .insert_resource(GameRng(ChaCha8Rng::seed_from_u64(*seed)))
+            .add_systems(OnEnter(InGame), reseed);
+    }
+}
+
+/// The seed [`GameRng`] is reset to at the start of every game
+#[derive(Resource, Debug, Clone, Copy, Deref)]
+pub struct RngSeed(u64);
+
+/// Source of randomness for gameplay.
+///
+/// Everything random that affects the simulation must come from here, or a
+/// run can't be reproduced from its [`RngSeed`].
+#[derive(Resource, Deref, DerefMut)]
+pub struct GameRng(ChaCha8Rng);
+
+/// Restart the random sequence so every game with the same seed plays out the same way
+fn reseed(seed: Res<RngSeed>, mut rng: ResMut<GameRng>) {
+    info!("random seed: {}", **seed);
+    **rng = ChaCha8Rng::seed_from_u64(**seed);
+}
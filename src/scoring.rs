@@ -0,0 +1,179 @@
+//! Points for broken bricks, combos and level bonuses.
+//!
+//! A brick is worth the points of its kind plus those of its row, see the
+//! config's `scoring` section. Every brick the balls hit without touching the
+//! paddle counts towards a combo, which raises the multiplier applied to those
+//! points. Clearing a level earns a bonus for the time left under par and for
+//! every life left.
+
+use std::time::Duration;
+
+use bevy::{prelude::*, time::Stopwatch};
+
+use crate::{
+    config::GameConfig,
+    level::StartLevel,
+    state::{screen_text, simulating, GameState, InGame},
+    BrickDestroyed, BrickKind, CollisionEvent, Hit, LifeLost, Lives, Score, SCORE_COLOR,
+};
+
+const POPUP_FONT_SIZE: f32 = 24.0;
+const POPUP_DURATION: Duration = Duration::from_millis(800);
+/// How fast popups drift upwards, in pixels per second
+const POPUP_RISE_SPEED: f32 = 40.0;
+// Above the bricks and the balls
+const POPUP_Z: f32 = 2.0;
+
+/// Plugin scoring broken bricks and cleared levels
+pub struct ScoringPlugin;
+
+impl Plugin for ScoringPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Combo>()
+            .init_resource::<LevelClock>()
+            .add_systems(OnEnter(InGame), start_level)
+            .add_systems(StartLevel, start_level)
+            .add_systems(OnEnter(GameState::LevelComplete), award_level_bonus)
+            .add_systems(
+                FixedUpdate,
+                (
+                    tick_level_clock,
+                    count_combo
+                        .after(crate::check_for_collisions)
+                        .before(crate::break_bricks),
+                    score_bricks.after(crate::break_bricks),
+                    break_combo
+                        .run_if(on_event::<LifeLost>)
+                        .after(crate::check_for_ball_lost),
+                )
+                    .run_if(simulating),
+            )
+            .add_systems(Update, animate_popups.run_if(in_state(InGame)));
+    }
+}
+
+/// Bricks hit since a ball last touched the paddle
+#[derive(Resource, Debug, Default)]
+pub struct Combo {
+    hits: u32,
+}
+
+impl Combo {
+    /// What brick points are multiplied by, going up by one every `combo_step` hits
+    pub fn multiplier(&self, config: &GameConfig) -> u32 {
+        let scoring = &config.scoring;
+        let steps = self.hits.saturating_sub(1) / scoring.combo_step.max(1);
+        (1 + steps).min(scoring.max_multiplier.max(1))
+    }
+}
+
+/// Time spent playing the current level
+#[derive(Resource, Default, Deref, DerefMut)]
+struct LevelClock(Stopwatch);
+
+/// A "+N" floating up from where points were scored
+#[derive(Component, Deref, DerefMut)]
+struct ScorePopup(Timer);
+
+fn start_level(mut combo: ResMut<Combo>, mut clock: ResMut<LevelClock>) {
+    *combo = Combo::default();
+    clock.reset();
+}
+
+fn tick_level_clock(mut clock: ResMut<LevelClock>, time: Res<Time>) {
+    clock.tick(time.delta());
+}
+
+/// Count the bricks hit towards the combo, until the paddle is touched
+fn count_combo(mut collision_events: EventReader<CollisionEvent>, mut combo: ResMut<Combo>) {
+    for event in collision_events.read() {
+        match event.hit {
+            Hit::Paddle => combo.hits = 0,
+            Hit::Brick { kind, .. } if kind != BrickKind::Unbreakable => combo.hits += 1,
+            _ => (),
+        }
+    }
+}
+
+/// Losing a ball ends the combo too
+fn break_combo(mut combo: ResMut<Combo>) {
+    combo.hits = 0;
+}
+
+/// Add up the points of every brick broken during this step
+fn score_bricks(
+    mut commands: Commands,
+    mut brick_destroyed_events: EventReader<BrickDestroyed>,
+    combo: Res<Combo>,
+    config: Res<GameConfig>,
+    mut score: ResMut<Score>,
+) {
+    let multiplier = combo.multiplier(&config) as usize;
+    for event in brick_destroyed_events.read() {
+        let row_points = config.scoring.row_points.get(event.row).copied();
+        let points = (event.kind.points() + row_points.unwrap_or(0)) * multiplier;
+        if points == 0 {
+            continue;
+        }
+        **score += points;
+        commands.spawn((
+            Text2d::new(format!("+{points}")),
+            TextFont {
+                font_size: POPUP_FONT_SIZE,
+                ..default()
+            },
+            TextColor(SCORE_COLOR),
+            Transform::from_translation(event.position.extend(POPUP_Z)),
+            ScorePopup(Timer::new(POPUP_DURATION, TimerMode::Once)),
+            StateScoped(InGame),
+        ));
+    }
+}
+
+/// Float popups upwards while they fade out
+fn animate_popups(
+    mut commands: Commands,
+    mut popups: Query<(Entity, &mut ScorePopup, &mut Transform, &mut TextColor)>,
+    time: Res<Time>,
+) {
+    for (entity, mut popup, mut transform, mut color) in &mut popups {
+        if popup.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.translation.y += POPUP_RISE_SPEED * time.delta_secs();
+        color.set_alpha(popup.fraction_remaining());
+    }
+}
+
+/// Reward the player for clearing the level quickly and with lives to spare
+fn award_level_bonus(
+    mut commands: Commands,
+    clock: Res<LevelClock>,
+    lives: Res<Lives>,
+    config: Res<GameConfig>,
+    mut score: ResMut<Score>,
+) {
+    let scoring = &config.scoring;
+    let seconds_under_par = (scoring.par_time - clock.elapsed_secs()).max(0.0) as usize;
+    let time_bonus = seconds_under_par * scoring.time_bonus;
+    let life_bonus = **lives * scoring.life_bonus;
+    **score += time_bonus + life_bonus;
+
+    // Shown over the level complete screen, below its hint
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                bottom: Val::Percent(30.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            GlobalZIndex(1),
+            StateScoped(GameState::LevelComplete),
+        ))
+        .with_child(screen_text(format!(
+            "Time bonus: +{time_bonus}   Lives bonus: +{life_bonus}"
+        )));
+}
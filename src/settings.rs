@@ -0,0 +1,438 @@
+//! Player settings, kept on disk between sessions, and the screen to change them.
+//!
+//! The volumes, the difficulty and the display options are saved as RON in the
+//! platform's config directory (for example `~/.config/mygame/settings.ron` on
+//! Linux), next to the input bindings. The settings screen is reached from the
+//! main menu and the pause menu, and leads on to the controls screen. The
+//! difficulty can only be changed between games, and `--difficulty` or a replay
+//! wins over the saved one.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    audio::Volume,
+    prelude::*,
+    window::{MonitorSelection, PresentMode, PrimaryWindow, WindowMode},
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    difficulty::Difficulty,
+    menu::{menu, spawn_menu_item, MenuAdjust, MenuBack, MenuPress},
+    state::{screen_root, screen_text, screen_title, GameState, PauseMenu},
+};
+
+/// Current version of the settings file format
+const FILE_VERSION: u32 = 1;
+const FILE_NAME: &str = "settings.ron";
+
+/// How much the volume goes up or down with each press, in percent
+const VOLUME_STEP: u32 = 10;
+const MAX_VOLUME: u32 = 100;
+
+/// Plugin loading, applying and saving the [`Settings`], and adding the settings screen
+pub struct SettingsPlugin {
+    /// Whether the difficulty was picked on the command line or by a replay,
+    /// rather than coming from the saved settings
+    pub difficulty_fixed: bool,
+}
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = Settings::load();
+        if !self.difficulty_fixed {
+            app.insert_resource(settings.difficulty);
+        }
+        app.insert_resource(settings)
+            .insert_resource(DifficultyFixed(self.difficulty_fixed))
+            .add_systems(
+                OnEnter(GameState::Settings),
+                spawn_settings_screen(GameState::Settings),
+            )
+            .add_systems(
+                OnEnter(PauseMenu::Settings),
+                spawn_settings_screen(PauseMenu::Settings),
+            )
+            .add_systems(
+                Update,
+                (
+                    apply_settings,
+                    update_settings_screen
+                        .run_if(in_state(GameState::Settings).or(in_state(PauseMenu::Settings))),
+                )
+                    .run_if(resource_changed::<Settings>),
+            );
+    }
+}
+
+/// Whether the [`Difficulty`] in play came from `--difficulty` or a replay,
+/// in which case the settings screen leaves it alone
+#[derive(Resource, Debug, Deref)]
+struct DifficultyFixed(bool);
+
+/// What the player has set up, kept on disk between sessions
+#[derive(Resource, Debug)]
+pub struct Settings {
+    /// Volume of every sound, in percent
+    pub master_volume: u32,
+    /// Volume of the music, in percent of the master volume
+    pub music_volume: u32,
+    /// Volume of the sound effects, in percent of the master volume
+    pub sfx_volume: u32,
+    /// Difficulty of new games
+    pub difficulty: Difficulty,
+    /// Whether the game covers the whole screen rather than sitting in a window
+    pub fullscreen: bool,
+    /// Whether frames wait for the display to be ready for them
+    pub vsync: bool,
+    /// Where the settings are saved, or `None` if they must not be written to
+    path: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            master_volume: MAX_VOLUME,
+            music_volume: MAX_VOLUME,
+            sfx_volume: MAX_VOLUME,
+            difficulty: Difficulty::default(),
+            fullscreen: false,
+            vsync: true,
+            path: None,
+        }
+    }
+}
+
+impl Settings {
+    /// How loud the music plays, all volumes included
+    pub fn music_volume(&self) -> Volume {
+        self.bus_volume(self.music_volume)
+    }
+
+    /// How loud the sound effects play, all volumes included
+    pub fn sfx_volume(&self) -> Volume {
+        self.bus_volume(self.sfx_volume)
+    }
+
+    fn bus_volume(&self, volume: u32) -> Volume {
+        let max = MAX_VOLUME as f32;
+        Volume::new(self.master_volume as f32 / max * volume as f32 / max)
+    }
+
+    fn load() -> Settings {
+        let Some(path) = settings_path() else {
+            warn!("no config directory found, settings won't be saved");
+            return Settings::default();
+        };
+
+        match read_settings(&path) {
+            Ok(Some(file)) => Settings {
+                master_volume: file.volume.min(MAX_VOLUME),
+                music_volume: file.music_volume.min(MAX_VOLUME),
+                sfx_volume: file.sfx_volume.min(MAX_VOLUME),
+                difficulty: file.difficulty,
+                fullscreen: file.fullscreen,
+                vsync: file.vsync,
+                path: Some(path),
+            },
+            Ok(None) => Settings {
+                path: Some(path),
+                ..default()
+            },
+            // Leave a file we can't make sense of alone rather than overwrite someone's settings
+            Err(error) => {
+                error!("{error}; settings won't be saved this session");
+                Settings::default()
+            }
+        }
+    }
+
+    fn save(&self) -> Result<(), SettingsError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = SettingsFile {
+            version: FILE_VERSION,
+            volume: self.master_volume,
+            music_volume: self.music_volume,
+            sfx_volume: self.sfx_volume,
+            difficulty: self.difficulty,
+            fullscreen: self.fullscreen,
+            vsync: self.vsync,
+        };
+        let contents = ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())?;
+
+        // Write to a temporary file first so that a crash can't leave half-written settings behind
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let temporary = path.with_extension("ron.tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+/// Possible errors while reading or writing the settings file
+#[derive(Debug, Error)]
+enum SettingsError {
+    #[error("could not access the settings file: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not parse the settings file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not write the settings file: {0}")]
+    Write(#[from] ron::Error),
+    #[error("the settings file has version {0}, but only versions up to {FILE_VERSION} are known")]
+    UnknownVersion(u32),
+}
+
+/// Just the version of a settings file, read before the rest of it
+#[derive(Deserialize)]
+struct FileVersion {
+    version: u32,
+}
+
+/// On-disk layout of the current [`FILE_VERSION`]
+#[derive(Serialize, Deserialize)]
+struct SettingsFile {
+    version: u32,
+    /// The master volume
+    volume: u32,
+    // Files saved before there were separate music and sound effect volumes lack these
+    #[serde(default = "max_volume")]
+    music_volume: u32,
+    #[serde(default = "max_volume")]
+    sfx_volume: u32,
+    difficulty: Difficulty,
+    fullscreen: bool,
+    vsync: bool,
+}
+
+fn max_volume() -> u32 {
+    MAX_VOLUME
+}
+
+fn settings_path() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join(env!("CARGO_PKG_NAME"))
+            .join(FILE_NAME),
+    )
+}
+
+/// Read the settings file, or `None` if there isn't one yet
+fn read_settings(path: &Path) -> Result<Option<SettingsFile>, SettingsError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    match ron::from_str::<FileVersion>(&contents)?.version {
+        FILE_VERSION => Ok(Some(ron::from_str(&contents)?)),
+        version => Err(SettingsError::UnknownVersion(version)),
+    }
+}
+
+/// Apply the display options, the volumes being up to the `sound` module
+fn apply_settings(settings: Res<Settings>, mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    for mut window in &mut windows {
+        window.mode = if settings.fullscreen {
+            WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+        } else {
+            WindowMode::Windowed
+        };
+        window.present_mode = if settings.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+    }
+}
+
+/// A line of the settings screen
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+enum SettingsItem {
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    Difficulty,
+    Fullscreen,
+    Vsync,
+    Controls,
+    Back,
+}
+
+impl SettingsItem {
+    /// Every line, from the top of the screen down
+    const ALL: [SettingsItem; 8] = [
+        SettingsItem::MasterVolume,
+        SettingsItem::MusicVolume,
+        SettingsItem::SfxVolume,
+        SettingsItem::Difficulty,
+        SettingsItem::Fullscreen,
+        SettingsItem::Vsync,
+        SettingsItem::Controls,
+        SettingsItem::Back,
+    ];
+
+    /// What the line says, with the current value of its setting.
+    /// The difficulty shown is the one in play, which is the saved one unless it is `fixed`.
+    fn label(
+        self,
+        settings: &Settings,
+        difficulty: Difficulty,
+        fixed: bool,
+        in_game: bool,
+    ) -> String {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        match self {
+            SettingsItem::MasterVolume => format!("Volume: {}%", settings.master_volume),
+            SettingsItem::MusicVolume => format!("Music: {}%", settings.music_volume),
+            SettingsItem::SfxVolume => format!("Sound effects: {}%", settings.sfx_volume),
+            SettingsItem::Difficulty if fixed => {
+                format!("Difficulty: {difficulty:?} (fixed for this session)")
+            }
+            SettingsItem::Difficulty if in_game => {
+                format!("Difficulty: {difficulty:?} (between games)")
+            }
+            SettingsItem::Difficulty => format!("Difficulty: {difficulty:?}"),
+            SettingsItem::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+            SettingsItem::Vsync => format!("Vsync: {}", on_off(settings.vsync)),
+            SettingsItem::Controls => "Controls".to_string(),
+            SettingsItem::Back => "Back".to_string(),
+        }
+    }
+}
+
+/// Returns a system that spawns the settings screen, despawned again when
+/// `state` is exited
+fn spawn_settings_screen<S: States + Copy>(
+    state: S,
+) -> impl Fn(Commands, Res<Settings>, Res<Difficulty>, Res<DifficultyFixed>, Option<Res<State<PauseMenu>>>)
+{
+    move |mut commands, settings, difficulty, fixed, pause_menu| {
+        commands.spawn(screen_root(state)).with_children(|p| {
+            p.spawn(screen_title("Settings"));
+            p.spawn(menu())
+                .observe(leave_settings::<MenuBack>)
+                .with_children(|p| {
+                    for item in SettingsItem::ALL {
+                        let label =
+                            item.label(&settings, *difficulty, **fixed, pause_menu.is_some());
+                        let mut entity = spawn_menu_item(p, label);
+                        entity.insert(item);
+                        match item {
+                            SettingsItem::Controls => entity.observe(open_controls),
+                            SettingsItem::Back => entity.observe(leave_settings::<MenuPress>),
+                            _ => entity
+                                .observe(step_settings_item)
+                                .observe(adjust_settings_item),
+                        };
+                    }
+                });
+            p.spawn(screen_text(
+                "Up/Down: choose, Left/Right or Enter: change, Esc: back",
+            ));
+        });
+    }
+}
+
+/// Back to the menu the settings screen was opened from
+fn leave_settings<E: Event>(
+    _: Trigger<E>,
+    pause_menu: Option<Res<State<PauseMenu>>>,
+    mut next_pause_menu: ResMut<NextState<PauseMenu>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if pause_menu.is_some() {
+        next_pause_menu.set(PauseMenu::Main);
+    } else {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+fn open_controls(
+    _: Trigger<MenuPress>,
+    pause_menu: Option<Res<State<PauseMenu>>>,
+    mut next_pause_menu: ResMut<NextState<PauseMenu>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if pause_menu.is_some() {
+        next_pause_menu.set(PauseMenu::Controls);
+    } else {
+        next_state.set(GameState::Controls);
+    }
+}
+
+/// Pressing a setting steps it along, as right does
+fn step_settings_item(trigger: Trigger<MenuPress>, mut commands: Commands) {
+    commands.trigger_targets(MenuAdjust(1), trigger.entity());
+}
+
+fn adjust_settings_item(
+    trigger: Trigger<MenuAdjust>,
+    items: Query<&SettingsItem>,
+    mut settings: ResMut<Settings>,
+    mut difficulty: ResMut<Difficulty>,
+    fixed: Res<DifficultyFixed>,
+    pause_menu: Option<Res<State<PauseMenu>>>,
+) {
+    let step = **trigger;
+    match items.get(trigger.entity()) {
+        Ok(SettingsItem::MasterVolume) => step_volume(&mut settings.master_volume, step),
+        Ok(SettingsItem::MusicVolume) => step_volume(&mut settings.music_volume, step),
+        Ok(SettingsItem::SfxVolume) => step_volume(&mut settings.sfx_volume, step),
+        // Changing the difficulty halfway through a game would change its replay too,
+        // and one picked on the command line or by a replay stays for the whole session
+        Ok(SettingsItem::Difficulty) if pause_menu.is_none() && !**fixed => {
+            let all = Difficulty::ALL;
+            let index = all.iter().position(|&d| d == settings.difficulty);
+            let index = index.map_or(0, |index| {
+                (index as i32 + step).rem_euclid(all.len() as i32) as usize
+            });
+            settings.difficulty = all[index];
+            *difficulty = settings.difficulty;
+        }
+        Ok(SettingsItem::Fullscreen) => settings.fullscreen = !settings.fullscreen,
+        Ok(SettingsItem::Vsync) => settings.vsync = !settings.vsync,
+        _ => return,
+    }
+    if let Err(error) = settings.save() {
+        error!("{error}");
+    }
+}
+
+fn step_volume(volume: &mut u32, step: i32) {
+    *volume = if step > 0 {
+        // Going up from the loudest wraps around to silence, so that Enter alone gets anywhere
+        if *volume >= MAX_VOLUME {
+            0
+        } else {
+            (*volume + VOLUME_STEP).min(MAX_VOLUME)
+        }
+    } else {
+        volume.saturating_sub(VOLUME_STEP)
+    };
+}
+
+/// Show the current value of every setting
+fn update_settings_screen(
+    settings: Res<Settings>,
+    difficulty: Res<Difficulty>,
+    fixed: Res<DifficultyFixed>,
+    pause_menu: Option<Res<State<PauseMenu>>>,
+    items: Query<(&SettingsItem, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    for (item, children) in &items {
+        let mut texts = texts.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.0 = item.label(&settings, *difficulty, **fixed, pause_menu.is_some());
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Sound effects and music.
+//!
+//! Which clip each [`CollisionEvent`] plays comes from a sound bank, a RON file
+//! in `assets/sounds/` mapping every [`SoundCue`] to a clip along with how fast
+//! and how loud to play it. Clip paths are relative to the bank file, and cues
+//! left out of the bank stay silent.
+//!
+//! ```ron
+//! (
+//!     pitch_variation: 0.05,
+//!     cues: {
+//!         Wall: (clip: "wall.ogg"),
+//!         BrickDestroyed: (clip: "brick.ogg", pitch: 1.2, volume: 0.8),
+//!     },
+//! )
+//! ```
+//!
+//! Every effect is played a little higher or lower than its `pitch`, by up to
+//! `pitch_variation`, so that the same bounce doesn't sound the same every time.
+//! Levels can name a track to loop while they are played, see the `level`
+//! module. How loud all of it is comes from the [`Settings`].
+
+use std::{collections::HashMap, path::Path};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    audio::{AudioSinkPlayback, Volume},
+    prelude::*,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    level::{CampaignLevels, CurrentLevel},
+    settings::Settings,
+    state::{simulating, GameState, InGame},
+    BrickKind, CollisionEvent, Hit,
+};
+
+/// Sound bank loaded at startup, relative to the `assets` directory
+const SOUND_BANK_PATH: &str = "sounds/breakout.sounds.ron";
+
+/// Plugin playing the sound effects of the [`SoundBank`] and the music of each level
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<SoundBank>()
+            .init_asset_loader::<SoundBankLoader>()
+            .add_systems(Startup, load_sound_bank)
+            .add_systems(
+                FixedUpdate,
+                play_collision_sounds
+                    .after(crate::check_for_collisions)
+                    .run_if(simulating),
+            )
+            .add_systems(
+                Update,
+                (
+                    play_level_music.run_if(in_state(InGame)),
+                    set_music_volume.run_if(resource_changed::<Settings>),
+                ),
+            )
+            .add_systems(OnEnter(GameState::Paused), pause_music)
+            .add_systems(OnExit(GameState::Paused), resume_music);
+    }
+}
+
+/// Something happening in the game that can make a sound
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum SoundCue {
+    /// A ball bounced off a wall or the ceiling
+    Wall,
+    /// A ball bounced off the paddle
+    Paddle,
+    /// A ball hit a brick that has hit points left
+    BrickDamaged,
+    /// A ball took a brick's last hit point
+    BrickDestroyed,
+    /// A ball bounced off an unbreakable brick
+    UnbreakableBrick,
+}
+
+impl From<Hit> for SoundCue {
+    fn from(hit: Hit) -> Self {
+        match hit {
+            Hit::Wall(_) => SoundCue::Wall,
+            Hit::Paddle => SoundCue::Paddle,
+            Hit::Brick {
+                kind: BrickKind::Unbreakable,
+                ..
+            } => SoundCue::UnbreakableBrick,
+            Hit::Brick {
+                destroyed: true, ..
+            } => SoundCue::BrickDestroyed,
+            Hit::Brick { .. } => SoundCue::BrickDamaged,
+        }
+    }
+}
+
+/// The clip played for each [`SoundCue`], loaded from a `.sounds.ron` file
+#[derive(Asset, TypePath, Debug)]
+pub struct SoundBank {
+    /// How far above or below its pitch each effect may be played, as a fraction of it
+    pitch_variation: f32,
+    cues: HashMap<SoundCue, Sound>,
+}
+
+/// A clip, and how to play it
+#[derive(Debug)]
+struct Sound {
+    clip: Handle<AudioSource>,
+    /// Playback speed, which raises or lowers the pitch along with it
+    pitch: f32,
+    /// Volume relative to the other effects
+    volume: f32,
+}
+
+/// On-disk representation of a [`SoundBank`]
+#[derive(Deserialize)]
+struct SoundBankFile {
+    #[serde(default)]
+    pitch_variation: f32,
+    cues: HashMap<SoundCue, SoundFile>,
+}
+
+/// On-disk representation of a [`Sound`].
+/// The clip path is relative to the bank file.
+#[derive(Deserialize)]
+struct SoundFile {
+    clip: String,
+    #[serde(default = "one")]
+    pitch: f32,
+    #[serde(default = "one")]
+    volume: f32,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+/// Possible errors produced by [`SoundBankLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum SoundBankLoaderError {
+    /// An [IO](std::io) Error
+    #[error("could not read sound bank file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON](ron) Error
+    #[error("could not parse sound bank file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+#[derive(Default)]
+struct SoundBankLoader;
+
+impl AssetLoader for SoundBankLoader {
+    type Asset = SoundBank;
+    type Settings = ();
+    type Error = SoundBankLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<SoundBank, SoundBankLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: SoundBankFile = ron::de::from_bytes(&bytes)?;
+
+        let directory = load_context
+            .path()
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let cues = file
+            .cues
+            .into_iter()
+            .map(|(cue, sound)| {
+                let sound = Sound {
+                    clip: load_context.load(directory.join(sound.clip)),
+                    pitch: sound.pitch,
+                    volume: sound.volume,
+                };
+                (cue, sound)
+            })
+            .collect();
+
+        Ok(SoundBank {
+            pitch_variation: file.pitch_variation,
+            cues,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sounds.ron"]
+    }
+}
+
+#[derive(Resource, Deref)]
+struct SoundBankHandle(Handle<SoundBank>);
+
+fn load_sound_bank(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(SoundBankHandle(asset_server.load(SOUND_BANK_PATH)));
+}
+
+/// Play the cue of every kind of collision of the step, once each however
+/// many balls bounced off the same kind of thing
+fn play_collision_sounds(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    bank: Res<SoundBankHandle>,
+    banks: Res<Assets<SoundBank>>,
+    settings: Res<Settings>,
+) {
+    let mut cues: Vec<SoundCue> = Vec::new();
+    for event in collision_events.read() {
+        let cue = SoundCue::from(event.hit);
+        if !cues.contains(&cue) {
+            cues.push(cue);
+        }
+    }
+    let Some(bank) = banks.get(&**bank) else {
+        return;
+    };
+
+    for cue in cues {
+        let Some(sound) = bank.cues.get(&cue) else {
+            continue;
+        };
+        // Kept apart from the game's own RNG so that sounds can't change how a replay plays out
+        let variation = bank.pitch_variation * (2.0 * rand::random::<f32>() - 1.0);
+        let volume = settings.sfx_volume().get() * sound.volume;
+        commands.spawn((
+            AudioPlayer(sound.clip.clone()),
+            PlaybackSettings::DESPAWN
+                .with_speed(sound.pitch * (1.0 + variation))
+                .with_volume(Volume::new(volume)),
+        ));
+    }
+}
+
+/// The music of the level being played, and where it was loaded from
+#[derive(Component, Debug)]
+struct LevelMusic(String);
+
+/// Start the current level's music whenever it differs from the one playing
+fn play_level_music(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    levels: CampaignLevels,
+    current_level: Res<CurrentLevel>,
+    settings: Res<Settings>,
+    playing: Query<(Entity, &LevelMusic)>,
+) {
+    let music = levels
+        .get(**current_level)
+        .and_then(|level| level.music.as_ref());
+    if playing.iter().map(|(_, playing)| &playing.0).eq(music) {
+        return;
+    }
+
+    for (entity, _) in &playing {
+        commands.entity(entity).despawn();
+    }
+    if let Some(music) = music {
+        commands.spawn((
+            AudioPlayer::<AudioSource>(asset_server.load(music)),
+            PlaybackSettings::LOOP.with_volume(settings.music_volume()),
+            LevelMusic(music.clone()),
+            StateScoped(InGame),
+        ));
+    }
+}
+
+fn set_music_volume(settings: Res<Settings>, sinks: Query<&AudioSink, With<LevelMusic>>) {
+    for sink in &sinks {
+        sink.set_volume(settings.music_volume().get());
+    }
+}
+
+fn pause_music(sinks: Query<&AudioSink, With<LevelMusic>>) {
+    for sink in &sinks {
+        sink.pause();
+    }
+}
+
+fn resume_music(sinks: Query<&AudioSink, With<LevelMusic>>) {
+    for sink in &sinks {
+        sink.play();
+    }
+}
@@ -0,0 +1,233 @@
+//! The game's top-level state machine and the simple full-screen prompts that
+//! go with each state.
+//!
+//! Every screen's text is set in `assets/fonts/FiraSans-Bold.ttf`, see
+//! [`screen_title`] and [`screen_text`].
+
+use bevy::{
+    ecs::{component::ComponentId, world::DeferredWorld},
+    prelude::*,
+};
+
+use crate::input::{Action, Actions};
+
+const UI_FONT_PATH: &str = "fonts/FiraSans-Bold.ttf";
+
+/// Which screen the game is currently on.
+///
+/// Gameplay systems only run in [`GameState::Playing`]; every other state
+/// either shows a menu or freezes the arena behind an overlay.
+#[derive(States, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameState {
+    /// Waiting for the levels to finish loading
+    #[default]
+    Loading,
+    /// Title screen, shown once everything is loaded and after a game has ended,
+    /// see the `menu` module
+    MainMenu,
+    /// Picking the level to start a game at, see the `menu` module
+    LevelSelect,
+    /// The high-score table, see the `highscore` module
+    HighScores,
+    /// Changing the volumes, difficulty and display, see the `settings` module
+    Settings,
+    /// Changing the input bindings, see the `input` module
+    Controls,
+    /// The ball is in play
+    Playing,
+    /// Gameplay is frozen but the arena stays on screen, see the `pause` module
+    Paused,
+    /// Every brick of the current level has been cleared
+    LevelComplete,
+    /// The player has run out of lives
+    GameOver,
+}
+
+/// Computed state that exists for as long as an arena is loaded.
+///
+/// Arena entities are scoped to this rather than to [`GameState::Playing`] so
+/// that pausing or finishing a level doesn't despawn them. Leaving for the main
+/// menu clears the whole arena so the next game starts from scratch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InGame;
+
+impl ComputedStates for InGame {
+    type SourceStates = GameState;
+
+    fn compute(sources: GameState) -> Option<Self> {
+        match sources {
+            GameState::Loading
+            | GameState::MainMenu
+            | GameState::LevelSelect
+            | GameState::HighScores
+            | GameState::Settings
+            | GameState::Controls => None,
+            GameState::Playing
+            | GameState::Paused
+            | GameState::LevelComplete
+            | GameState::GameOver => Some(InGame),
+        }
+    }
+}
+
+/// Which screen of the pause menu is showing
+#[derive(SubStates, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[source(GameState = GameState::Paused)]
+pub enum PauseMenu {
+    #[default]
+    Main,
+    /// The settings screen, see the `settings` module
+    Settings,
+    /// The controls screen, see the `input` module
+    Controls,
+}
+
+/// Plugin registering [`GameState`], [`InGame`], [`PauseMenu`] and the prompt screens
+pub struct GameStatePlugin;
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<GameState>()
+            .add_computed_state::<InGame>()
+            .add_sub_state::<PauseMenu>()
+            .enable_state_scoped_entities::<GameState>()
+            .enable_state_scoped_entities::<InGame>()
+            .enable_state_scoped_entities::<PauseMenu>()
+            .add_systems(Startup, load_ui_font)
+            .add_systems(
+                OnEnter(GameState::LevelComplete),
+                spawn_screen(
+                    "Level cleared!",
+                    "Press Enter to continue",
+                    GameState::LevelComplete,
+                ),
+            )
+            .add_systems(Update, handle_state_input)
+            .init_resource::<Simulating>()
+            .add_systems(FixedFirst, update_simulating);
+    }
+}
+
+/// Whether the gameplay systems run during the current fixed step, see [`simulating`]
+#[derive(Resource, Default)]
+pub struct Simulating(bool);
+
+/// Run condition for the fixed-step gameplay systems.
+///
+/// True while in [`GameState::Playing`], but only until a change of state is
+/// requested. Once a level is cleared or the last ball is lost, the fixed steps
+/// left in that frame are skipped, so a game plays out the same however many
+/// fixed steps each frame happens to hold. The answer is worked out once at the
+/// start of every step, so a step is never half run.
+pub fn simulating(simulating: Res<Simulating>) -> bool {
+    simulating.0
+}
+
+fn update_simulating(
+    state: Res<State<GameState>>,
+    next_state: Res<NextState<GameState>>,
+    mut simulating: ResMut<Simulating>,
+) {
+    simulating.0 =
+        *state.get() == GameState::Playing && matches!(*next_state, NextState::Unchanged);
+}
+
+/// Font of the text on every screen
+#[derive(Resource)]
+struct UiFont(Handle<Font>);
+
+fn load_ui_font(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(UiFont(asset_server.load(UI_FONT_PATH)));
+}
+
+/// Marks text spawned by [`screen_title`] and [`screen_text`], which is set in the [`UiFont`]
+/// as soon as it is added
+#[derive(Component)]
+#[component(on_add = use_ui_font)]
+struct ScreenText;
+
+fn use_ui_font(mut world: DeferredWorld, entity: Entity, _: ComponentId) {
+    let Some(font) = world.get_resource::<UiFont>().map(|font| font.0.clone()) else {
+        return;
+    };
+    if let Some(mut text_font) = world.get_mut::<TextFont>(entity) {
+        text_font.font = font;
+    }
+}
+
+const SCREEN_TITLE_FONT_SIZE: f32 = 60.0;
+const SCREEN_HINT_FONT_SIZE: f32 = 25.0;
+const SCREEN_TEXT_COLOR: Color = Color::srgb(0.2, 0.2, 0.4);
+const SCREEN_BACKGROUND_COLOR: Color = Color::srgba(0.9, 0.9, 0.9, 0.8);
+
+/// Full-screen node centering its children in a column over a translucent
+/// background, despawned when `state` is exited
+pub fn screen_root<S: States>(state: S) -> impl Bundle {
+    (
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(20.0),
+            ..default()
+        },
+        BackgroundColor(SCREEN_BACKGROUND_COLOR),
+        StateScoped(state),
+    )
+}
+
+/// Large heading text for a screen
+pub fn screen_title(title: impl Into<String>) -> impl Bundle {
+    (
+        Text::new(title),
+        TextFont {
+            font_size: SCREEN_TITLE_FONT_SIZE,
+            ..default()
+        },
+        TextColor(SCREEN_TEXT_COLOR),
+        ScreenText,
+    )
+}
+
+/// Body text for a screen
+pub fn screen_text(text: impl Into<String>) -> impl Bundle {
+    (
+        Text::new(text),
+        TextFont {
+            font_size: SCREEN_HINT_FONT_SIZE,
+            ..default()
+        },
+        TextColor(SCREEN_TEXT_COLOR),
+        ScreenText,
+    )
+}
+
+/// Returns a system that spawns a centered title and hint, despawned again
+/// when `state` is exited.
+fn spawn_screen(title: &'static str, hint: &'static str, state: GameState) -> impl Fn(Commands) {
+    move |mut commands: Commands| {
+        commands.spawn(screen_root(state)).with_children(|p| {
+            p.spawn(screen_title(title));
+            p.spawn(screen_text(hint));
+        });
+    }
+}
+
+/// Transitions between the states on the player's actions
+fn handle_state_input(
+    actions: Res<Actions>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let next = match state.get() {
+        GameState::Playing if actions.just_pressed(Action::Pause) => GameState::Paused,
+        // The menus are left from the menus themselves, see the `menu` module.
+        // Leaving `Paused` is up to the pause menu, see the `pause` module.
+        // Leaving `LevelComplete` depends on the campaign, see `level::advance_level`,
+        // and leaving `GameOver` may have to wait for a high score name, see `highscore`
+        _ => return,
+    };
+    next_state.set(next);
+}
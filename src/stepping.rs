@@ -1,4 +1,16 @@
-use bevy::{app::MainScheduleOrder, ecs::schedule::*, prelude::*};
+use bevy::{
+    app::MainScheduleOrder,
+    core::FrameCount,
+    ecs::{
+        schedule::*,
+        system::{BoxedSystem, EntityCommands},
+    },
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    picking::focus::HoverMap,
+    prelude::*,
+};
+
+use crate::input::{Action, Actions, InputBindings};
 
 /// Independent [`Schedule`] for stepping systems.
 ///
@@ -9,10 +21,16 @@ use bevy::{app::MainScheduleOrder, ecs::schedule::*, prelude::*};
 #[derive(Debug, Hash, PartialEq, Eq, Clone, ScheduleLabel)]
 struct DebugSchedule;
 
-/// Plugin to add a stepping UI to an example
+/// Plugin to add a stepping UI to an example.
+///
+/// More schedules can be stepped, or stop being stepped, while the app runs by
+/// sending a [`SteppingScheduleEvent`]. The panel follows the systems of the
+/// stepped schedules as they change, and lists the schedules that haven't been
+/// run yet without their systems until they are.
 #[derive(Default)]
 pub struct SteppingPlugin {
     schedule_labels: Vec<InternedScheduleLabel>,
+    filter: SystemFilter,
     top: Val,
     left: Val,
 }
@@ -24,7 +42,20 @@ impl SteppingPlugin {
         self
     }
 
-    /// Set the location of the stepping UI when activated
+    /// Only step the systems whose path matches one of the patterns given this way,
+    /// see [`SystemFilter`]
+    pub fn include(mut self, pattern: impl Into<String>) -> SteppingPlugin {
+        self.filter.include.push(pattern.into());
+        self
+    }
+
+    /// Never step the systems whose path matches `pattern`, see [`SystemFilter`]
+    pub fn exclude(mut self, pattern: impl Into<String>) -> SteppingPlugin {
+        self.filter.exclude.push(pattern.into());
+        self
+    }
+
+    /// Set where the stepping UI first shows up when activated; it can be dragged from there
     pub fn at(self, left: Val, top: Val) -> SteppingPlugin {
         SteppingPlugin { top, left, ..self }
     }
@@ -32,7 +63,8 @@ impl SteppingPlugin {
 
 impl Plugin for SteppingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, build_stepping_hint);
+        app.add_event::<SteppingScheduleEvent>()
+            .add_systems(Startup, build_stepping_hint);
         if cfg!(not(feature = "bevy_debug_stepping")) {
             return;
         }
@@ -49,19 +81,31 @@ impl Plugin for SteppingPlugin {
         for label in &self.schedule_labels {
             stepping.add_schedule(*label);
         }
-        app.insert_resource(stepping);
+        app.insert_resource(stepping)
+            .insert_resource(self.filter.clone());
 
         // add our startup & stepping systems
         app.insert_resource(State {
             ui_top: self.top,
             ui_left: self.left,
+            schedules: self.schedule_labels.clone(),
+            removed: Vec::new(),
+            graph: Vec::new(),
             systems: Vec::new(),
+            selected: 0,
+            run_to: None,
+            filtered_out: Vec::new(),
+            collapsed: false,
         })
         .add_systems(
             DebugSchedule,
             (
+                change_schedules,
+                refresh_ui.run_if(resource_changed::<SystemFilter>.or(schedules_changed)),
                 build_ui.run_if(not(initialized)),
                 handle_input,
+                run_to_system,
+                scroll_system_list,
                 update_ui.run_if(initialized),
             )
                 .chain(),
@@ -69,124 +113,741 @@ impl Plugin for SteppingPlugin {
     }
 }
 
+/// Which systems of the stepped schedules can be stepped, by their path, such
+/// as `mygame::check_for_collisions` or `mygame::level::spawn_bricks`.
+///
+/// Patterns may use `*` for any run of characters and `?` for any single one,
+/// so `mygame::*` allows a whole crate. Every other system keeps running while
+/// stepping. Changing the filter rebuilds the stepping panel, and the systems
+/// still listed keep their breakpoints.
+#[derive(Resource, Debug, Clone)]
+pub struct SystemFilter {
+    /// Patterns of the systems to step, or empty to step every system
+    pub include: Vec<String>,
+    /// Patterns of the systems never to step, even if they are included
+    pub exclude: Vec<String>,
+    /// Whether the patterns apply at all, rather than stepping every system
+    pub enabled: bool,
+}
+
+impl Default for SystemFilter {
+    /// Steps everything but Bevy's own systems
+    fn default() -> Self {
+        SystemFilter {
+            include: Vec::new(),
+            exclude: vec!["bevy*".to_string()],
+            enabled: true,
+        }
+    }
+}
+
+impl SystemFilter {
+    /// Whether the system at `path` is stepped
+    pub fn matches(&self, path: &str) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let included =
+            self.include.is_empty() || self.include.iter().any(|pattern| glob_match(pattern, path));
+        included && !self.exclude.iter().any(|pattern| glob_match(pattern, path))
+    }
+}
+
+/// Starts or stops stepping a schedule while the app runs
+#[derive(Event, Debug, Clone, Copy)]
+pub enum SteppingScheduleEvent {
+    /// Step the systems of this schedule too
+    Add(InternedScheduleLabel),
+    /// Stop stepping this schedule, forgetting the breakpoints of its systems
+    Remove(InternedScheduleLabel),
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any run of characters
+/// and `?` for any single one
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was seen, and where in the text it started matching
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            // Let the last `*` swallow one more character and try again from there
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 /// Struct for maintaining stepping state
 #[derive(Resource, Debug)]
 struct State {
-    // vector of schedule/nodeid -> text index offset
-    systems: Vec<(InternedScheduleLabel, NodeId, usize)>,
+    // schedules being stepped, in the order they were added
+    schedules: Vec<InternedScheduleLabel>,
+    // schedules that were stepped until a `SteppingScheduleEvent` removed them
+    removed: Vec<InternedScheduleLabel>,
+    // systems of the stepped schedules when the panel was built
+    graph: ScheduleGraph,
+    // every system that can be stepped, in the order they are listed
+    systems: Vec<SteppingSystem>,
+    // index into `systems` of the system the breakpoint and skip keys act on
+    selected: usize,
+    // index into `systems` of the system being run to, whose breakpoint only
+    // lasts until it is reached
+    run_to: Option<usize>,
+    // systems left out by the `SystemFilter`, which always run
+    filtered_out: Vec<(InternedScheduleLabel, NodeId)>,
 
-    // ui positioning
+    // ui positioning, kept when the panel is rebuilt
     ui_top: Val,
     ui_left: Val,
+    // whether the system list is hidden, kept when the panel is rebuilt
+    collapsed: bool,
+}
+
+/// The nodes of the systems of each stepped schedule, in the order they are
+/// listed, or `None` for the schedules that haven't been run yet
+type ScheduleGraph = Vec<(InternedScheduleLabel, Option<Vec<NodeId>>)>;
+
+/// A line of the stepping UI
+#[derive(Debug)]
+struct SteppingSystem {
+    schedule: InternedScheduleLabel,
+    node: NodeId,
+    behavior: SystemBehavior,
+}
+
+/// What stepping does with a system, which [`Stepping`] keeps to itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SystemBehavior {
+    /// Stepped through like any other system
+    Continue,
+    /// Stops `continue_frame` before the system runs
+    Break,
+    /// Never runs while stepping
+    Skip,
+    /// Runs every frame while stepping
+    AlwaysRun,
+}
+
+impl SystemBehavior {
+    /// Every behavior with a button of its own, in the order the buttons are shown
+    const TOGGLED: [SystemBehavior; 3] = [
+        SystemBehavior::Break,
+        SystemBehavior::Skip,
+        SystemBehavior::AlwaysRun,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            SystemBehavior::Continue => "continue",
+            SystemBehavior::Break => "break",
+            SystemBehavior::Skip => "skip",
+            SystemBehavior::AlwaysRun => "always",
+        }
+    }
+}
+
+/// Tell [`Stepping`] what to do with `system`
+fn apply_behavior(stepping: &mut Stepping, system: &SteppingSystem) {
+    let (schedule, node) = (system.schedule, system.node);
+    match system.behavior {
+        SystemBehavior::Continue => stepping.clear_node(schedule, node),
+        SystemBehavior::Break => stepping.set_breakpoint_node(schedule, node),
+        SystemBehavior::Skip => stepping.never_run_node(schedule, node),
+        SystemBehavior::AlwaysRun => stepping.always_run_node(schedule, node),
+    };
+}
+
+/// Give the system at `index` the `toggled` behavior, or take it away if it already has it
+fn toggle_behavior(
+    stepping: &mut Stepping,
+    state: &mut State,
+    index: usize,
+    toggled: SystemBehavior,
+) {
+    let Some(system) = state.systems.get_mut(index) else {
+        return;
+    };
+    system.behavior = if system.behavior == toggled {
+        SystemBehavior::Continue
+    } else {
+        toggled
+    };
+    debug!("{:?} is now {:?}", system.node, system.behavior);
+    // A system being run to keeps its breakpoint until it is reached
+    if state.run_to != Some(index) {
+        apply_behavior(stepping, system);
+    }
+}
+
+/// Run on to the system at `index`, however many frames that takes
+fn start_run_to(stepping: &mut Stepping, state: &mut State, index: usize) {
+    if !stepping.is_enabled() {
+        return;
+    }
+    if let Some(previous) = state
+        .run_to
+        .take()
+        .and_then(|index| state.systems.get(index))
+    {
+        apply_behavior(stepping, previous);
+    }
+    let Some(system) = state.systems.get(index) else {
+        return;
+    };
+    stepping.set_breakpoint_node(system.schedule, system.node);
+    stepping.continue_frame();
+    state.run_to = Some(index);
 }
 
 /// condition to check if the stepping UI has been constructed
-fn initialized(state: Res<State>) -> bool {
-    !state.systems.is_empty()
+fn initialized(ui: Query<(), With<SteppingUi>>) -> bool {
+    !ui.is_empty()
+}
+
+/// The stepped schedules in the order they run, as far as [`Stepping`] has
+/// seen them run, followed by the others in the order they were added
+fn schedule_order(stepping: &Stepping, state: &State) -> Vec<InternedScheduleLabel> {
+    let mut order: Vec<InternedScheduleLabel> = match stepping.schedules() {
+        Ok(order) => order
+            .iter()
+            .filter(|label| state.schedules.contains(label))
+            .copied()
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    for label in &state.schedules {
+        if !order.contains(label) {
+            order.push(*label);
+        }
+    }
+    order
+}
+
+/// The systems of the schedule in the order the single-threaded executor would
+/// run them, or `None` if the schedule hasn't been run yet
+fn schedule_systems(
+    schedules: &Schedules,
+    label: InternedScheduleLabel,
+) -> Option<impl Iterator<Item = (NodeId, &BoxedSystem)>> {
+    schedules.get(label)?.systems().ok()
+}
+
+/// condition to check if systems were added to the stepped schedules, or
+/// schedules were added, removed or run for the first time, since the stepping
+/// UI was constructed
+fn schedules_changed(
+    schedules: Res<Schedules>,
+    stepping: Res<Stepping>,
+    state: Res<State>,
+) -> bool {
+    let order = schedule_order(&stepping, &state);
+    order.len() != state.graph.len()
+        || order
+            .iter()
+            .zip(&state.graph)
+            .any(|(label, (listed, nodes))| {
+                let current = schedule_systems(&schedules, *label);
+                label != listed
+                    || match (current, nodes) {
+                        (Some(current), Some(nodes)) => {
+                            !current.map(|(node, _)| node).eq(nodes.iter().copied())
+                        }
+                        (None, None) => false,
+                        _ => true,
+                    }
+            })
+}
+
+/// Start or stop stepping the schedules of every [`SteppingScheduleEvent`]
+fn change_schedules(
+    mut events: EventReader<SteppingScheduleEvent>,
+    mut stepping: ResMut<Stepping>,
+    mut state: ResMut<State>,
+) {
+    for event in events.read() {
+        match *event {
+            SteppingScheduleEvent::Add(label) => {
+                if state.schedules.contains(&label) {
+                    continue;
+                }
+                stepping.add_schedule(label);
+                state.schedules.push(label);
+                state.removed.retain(|removed| *removed != label);
+            }
+            SteppingScheduleEvent::Remove(label) => {
+                let Some(index) = state.schedules.iter().position(|stepped| *stepped == label)
+                else {
+                    continue;
+                };
+                stepping.remove_schedule(label);
+                state.schedules.remove(index);
+                state.removed.push(label);
+            }
+        }
+        debug!("{event:?}");
+    }
 }
 
 const FONT_COLOR: Color = Color::srgb(0.2, 0.2, 0.2);
+const SELECTED_FONT_COLOR: Color = Color::srgb(0.8, 0.1, 0.1);
 const FONT_BOLD: &str = "fonts/FiraSans-Bold.ttf";
+const BUTTON_FONT_SIZE: f32 = 13.0;
+const BUTTON_COLOR: Color = Color::srgba(0.8, 0.8, 0.8, 0.8);
+const BUTTON_ACTIVE_COLOR: Color = Color::srgb(0.95, 0.6, 0.3);
+const PANEL_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.33);
+/// How far the list scrolls for each line the mouse wheel turns
+const SCROLL_LINE_HEIGHT: f32 = 20.0;
 
 #[derive(Component)]
 struct SteppingUi;
 
+/// Text at the top of the panel, showing the frame and where the cursor is
+#[derive(Component)]
+struct SteppingHeader;
+
+/// The scrolling list of systems, hidden while the panel is collapsed
+#[derive(Component)]
+struct SystemList;
+
+/// Label of the button collapsing and expanding the panel
+#[derive(Component)]
+struct CollapseLabel;
+
+/// Label of the button turning the [`SystemFilter`] on and off
+#[derive(Component)]
+struct FilterLabel;
+
+/// Text showing whether the stepping cursor is at the system at this index
+#[derive(Component)]
+struct SystemCursor(usize);
+
+/// Name of the system at this index
+#[derive(Component)]
+struct SystemName(usize);
+
+/// Button toggling a behavior of the system at `system`
+#[derive(Component)]
+struct BehaviorButton {
+    system: usize,
+    behavior: SystemBehavior,
+}
+
+/// A line of the stepping UI, before it is spawned
+enum Line {
+    /// A stepped schedule, and whether it has been run yet
+    Schedule {
+        label: InternedScheduleLabel,
+        run: bool,
+    },
+    /// A schedule that was stepped until it was removed
+    Removed(InternedScheduleLabel),
+    System {
+        index: usize,
+        name: String,
+    },
+}
+
 /// Construct the stepping UI elements from the [`Schedules`] resource.
 ///
-/// This system may run multiple times before constructing the UI as all of the
-/// data may not be available on the first run of the system.  This happens if
-/// one of the stepping schedules has not yet been run.
+/// Schedules that have not been run yet are listed without their systems, and
+/// [`schedules_changed`] has the UI constructed again once they have.
 fn build_ui(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     schedules: Res<Schedules>,
+    filter: Res<SystemFilter>,
     mut stepping: ResMut<Stepping>,
     mut state: ResMut<State>,
 ) {
-    let mut text_spans = Vec::new();
+    let mut lines = Vec::new();
+    let mut graph = ScheduleGraph::new();
+    let mut systems: Vec<SteppingSystem> = Vec::new();
     let mut always_run = Vec::new();
 
-    let Ok(schedule_order) = stepping.schedules() else {
-        return;
-    };
-
     // go through the stepping schedules and construct a list of systems for
     // each label
-    for label in schedule_order {
-        let schedule = schedules.get(*label).unwrap();
-        text_spans.push((
-            TextSpan(format!("{label:?}\n")),
-            TextFont {
-                font: asset_server.load(FONT_BOLD),
-                ..default()
-            },
-            TextColor(FONT_COLOR),
-        ));
-
-        // grab the list of systems in the schedule, in the order the
-        // single-threaded executor would run them.
-        let Ok(systems) = schedule.systems() else {
-            return;
+    for label in schedule_order(&stepping, &state) {
+        let Some(schedule_systems) = schedule_systems(&schedules, label) else {
+            lines.push(Line::Schedule { label, run: false });
+            graph.push((label, None));
+            continue;
         };
+        lines.push(Line::Schedule { label, run: true });
 
-        for (node_id, system) in systems {
-            // skip bevy default systems; we don't want to step those
-            if system.name().starts_with("bevy") {
-                always_run.push((*label, node_id));
+        let mut nodes = Vec::new();
+        for (node_id, system) in schedule_systems {
+            nodes.push(node_id);
+            // skip the systems we don't want to step, such as bevy's own
+            if !filter.matches(&system.name()) {
+                always_run.push((label, node_id));
                 continue;
             }
 
-            // Add an entry to our systems list so we can find where to draw
-            // the cursor when the stepping cursor is at this system
-            // we add plus 1 to account for the empty root span
-            state.systems.push((*label, node_id, text_spans.len() + 1));
-
-            // Add a text section for displaying the cursor for this system
-            text_spans.push((
-                TextSpan::new("   "),
-                TextFont::default(),
-                TextColor(FONT_COLOR),
-            ));
-
-            // add the name of the system to the ui
-            text_spans.push((
-                TextSpan(format!("{}\n", system.name())),
-                TextFont::default(),
-                TextColor(FONT_COLOR),
-            ));
+            // Systems that were listed before the panel was rebuilt keep their behavior
+            let behavior = state
+                .systems
+                .iter()
+                .find(|listed| listed.schedule == label && listed.node == node_id)
+                .map_or(SystemBehavior::Continue, |listed| listed.behavior);
+            lines.push(Line::System {
+                index: systems.len(),
+                name: system.name().to_string(),
+            });
+            systems.push(SteppingSystem {
+                schedule: label,
+                node: node_id,
+                behavior,
+            });
         }
+        graph.push((label, Some(nodes)));
     }
+    lines.extend(state.removed.iter().copied().map(Line::Removed));
 
-    for (label, node) in always_run.drain(..) {
+    for &(label, node) in &always_run {
         stepping.always_run_node(label, node);
     }
+    // The selection stays on the same system wherever it is listed now
+    let selected = state
+        .systems
+        .get(state.selected)
+        .and_then(|selected| {
+            systems.iter().position(|system| {
+                system.schedule == selected.schedule && system.node == selected.node
+            })
+        })
+        .unwrap_or(state.selected);
+    state.filtered_out = always_run;
+    state.graph = graph;
+    state.systems = systems;
+    state.selected = selected.min(state.systems.len().saturating_sub(1));
 
+    let bold = TextFont {
+        font: asset_server.load(FONT_BOLD),
+        ..default()
+    };
+    let collapsed = state.collapsed;
     commands
         .spawn((
-            Text::default(),
             SteppingUi,
             Node {
                 position_type: PositionType::Absolute,
                 top: state.ui_top,
                 left: state.ui_left,
+                max_height: Val::Vh(90.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.0),
                 padding: UiRect::all(Val::Px(10.0)),
                 ..default()
             },
-            BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.33)),
+            BackgroundColor(PANEL_COLOR),
             Visibility::Hidden,
         ))
         .with_children(|p| {
-            for span in text_spans {
-                p.spawn(span);
+            p.spawn(Node {
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(20.0),
+                ..default()
+            })
+            .observe(drag_panel)
+            .with_children(|p| {
+                p.spawn((
+                    Text::default(),
+                    bold.clone(),
+                    TextColor(FONT_COLOR),
+                    SteppingHeader,
+                ));
+                p.spawn(Node {
+                    column_gap: Val::Px(4.0),
+                    ..default()
+                })
+                .with_children(|p| {
+                    let filter_label = if filter.enabled {
+                        "filter: on"
+                    } else {
+                        "filter: off"
+                    };
+                    spawn_button(p, filter_label, FilterLabel).observe(toggle_filter);
+                    spawn_button(p, collapse_label(collapsed), CollapseLabel)
+                        .observe(toggle_collapsed);
+                });
+            });
+
+            p.spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    // Lets the list shrink to fit the panel, and scroll instead
+                    min_height: Val::Px(0.0),
+                    overflow: Overflow::scroll_y(),
+                    display: list_display(collapsed),
+                    ..default()
+                },
+                ScrollPosition::default(),
+                SystemList,
+            ))
+            .with_children(|p| {
+                for line in lines {
+                    match line {
+                        Line::Schedule { label, run } => {
+                            let title = if run {
+                                format!("{label:?}")
+                            } else {
+                                format!("{label:?} (not run yet)")
+                            };
+                            let event = SteppingScheduleEvent::Remove(label);
+                            spawn_schedule_row(p, title, &bold, "remove", event);
+                        }
+                        Line::Removed(label) => {
+                            let title = format!("{label:?} (not stepped)");
+                            let event = SteppingScheduleEvent::Add(label);
+                            spawn_schedule_row(p, title, &bold, "add", event);
+                        }
+                        Line::System { index, name } => spawn_system_row(p, index, name),
+                    }
+                }
+            });
+        });
+}
+
+/// A schedule's row of the system list, with a button labelled `button` sending `event`
+fn spawn_schedule_row(
+    parent: &mut ChildBuilder,
+    title: String,
+    font: &TextFont,
+    button: &str,
+    event: SteppingScheduleEvent,
+) {
+    parent
+        .spawn(Node {
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(8.0),
+            ..default()
+        })
+        .with_children(|p| {
+            p.spawn((Text(title), font.clone(), TextColor(FONT_COLOR)));
+            spawn_button(p, button, ()).observe(
+                move |_: Trigger<Pointer<Click>>,
+                      mut events: EventWriter<SteppingScheduleEvent>| {
+                    events.send(event);
+                },
+            );
+        });
+}
+
+/// A row of the system list: the cursor, the name, and buttons for what
+/// stepping does with the system
+fn spawn_system_row(parent: &mut ChildBuilder, index: usize, name: String) {
+    parent
+        .spawn(Node {
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(4.0),
+            ..default()
+        })
+        .observe(move |_: Trigger<Pointer<Click>>, mut state: ResMut<State>| state.selected = index)
+        .with_children(|p| {
+            p.spawn((Text::new("   "), TextColor(FONT_COLOR), SystemCursor(index)));
+            spawn_button(p, "run to", ()).observe(
+                move |_: Trigger<Pointer<Click>>,
+                      mut stepping: ResMut<Stepping>,
+                      mut state: ResMut<State>| {
+                    start_run_to(&mut stepping, &mut state, index);
+                },
+            );
+            for behavior in SystemBehavior::TOGGLED {
+                spawn_button(p, behavior.label(), ())
+                    .insert(BehaviorButton {
+                        system: index,
+                        behavior,
+                    })
+                    .observe(
+                        move |_: Trigger<Pointer<Click>>,
+                              mut stepping: ResMut<Stepping>,
+                              mut state: ResMut<State>| {
+                            toggle_behavior(&mut stepping, &mut state, index, behavior);
+                        },
+                    );
             }
+            p.spawn((Text(name), TextColor(FONT_COLOR), SystemName(index)));
         });
 }
 
-fn build_stepping_hint(mut commands: Commands) {
+/// Add a small button labelled `label` to the node being built, with `marker` on its text
+fn spawn_button<'a>(
+    parent: &'a mut ChildBuilder,
+    label: &str,
+    marker: impl Bundle,
+) -> EntityCommands<'a> {
+    let mut button = parent.spawn((
+        Button,
+        Node {
+            padding: UiRect::axes(Val::Px(4.0), Val::Px(1.0)),
+            ..default()
+        },
+        BackgroundColor(BUTTON_COLOR),
+    ));
+    button.with_child((
+        Text::new(label),
+        TextFont {
+            font_size: BUTTON_FONT_SIZE,
+            ..default()
+        },
+        TextColor(FONT_COLOR),
+        marker,
+    ));
+    button
+}
+
+/// Throw the panel away to be built again by [`build_ui`], after the
+/// [`SystemFilter`] or the stepped schedules changed
+fn refresh_ui(
+    mut commands: Commands,
+    mut stepping: ResMut<Stepping>,
+    mut state: ResMut<State>,
+    ui: Query<Entity, With<SteppingUi>>,
+) {
+    for entity in &ui {
+        commands.entity(entity).despawn_recursive();
+    }
+    // Removing a schedule from `Stepping` already forgot about its systems
+    let state = &mut *state;
+    let stepped = |label: &InternedScheduleLabel| state.schedules.contains(label);
+    // Systems left out until now may be stepped from now on
+    for (label, node) in state.filtered_out.drain(..) {
+        if stepped(&label) {
+            stepping.clear_node(label, node);
+        }
+    }
+    // The system being run to may have gone from the stepped schedules since the panel was built
+    if let Some(system) = state
+        .run_to
+        .take()
+        .and_then(|index| state.systems.get(index))
+    {
+        if stepped(&system.schedule) {
+            apply_behavior(&mut stepping, system);
+        }
+    }
+}
+
+fn toggle_filter(_: Trigger<Pointer<Click>>, mut filter: ResMut<SystemFilter>) {
+    filter.enabled = !filter.enabled;
+}
+
+/// Move the panel along with the pointer dragging its header
+fn drag_panel(
+    trigger: Trigger<Pointer<Drag>>,
+    mut state: ResMut<State>,
+    mut panels: Query<(&mut Node, &ComputedNode, &GlobalTransform), With<SteppingUi>>,
+) {
+    for (mut node, computed, transform) in &mut panels {
+        let position = match (node.left, node.top) {
+            (Val::Px(left), Val::Px(top)) => Vec2::new(left, top),
+            // Placed some other way, so start from where the layout put it
+            _ => {
+                (transform.translation().truncate() - computed.size() / 2.)
+                    * computed.inverse_scale_factor()
+            }
+        };
+        let position = position + trigger.delta;
+        node.left = Val::Px(position.x);
+        node.top = Val::Px(position.y);
+        state.ui_left = node.left;
+        state.ui_top = node.top;
+    }
+}
+
+fn toggle_collapsed(
+    _: Trigger<Pointer<Click>>,
+    mut state: ResMut<State>,
+    mut lists: Query<&mut Node, With<SystemList>>,
+    mut labels: Query<&mut Text, With<CollapseLabel>>,
+) {
+    state.collapsed = !state.collapsed;
+    for mut list in &mut lists {
+        list.display = list_display(state.collapsed);
+    }
+    for mut label in &mut labels {
+        label.0 = collapse_label(state.collapsed).to_string();
+    }
+}
+
+/// How the system list is laid out, depending on whether the panel is collapsed
+fn list_display(collapsed: bool) -> Display {
+    if collapsed {
+        Display::None
+    } else {
+        Display::Flex
+    }
+}
+
+/// Label of the button expanding the panel if it is collapsed, or collapsing it
+fn collapse_label(collapsed: bool) -> &'static str {
+    if collapsed {
+        "+"
+    } else {
+        "-"
+    }
+}
+
+/// Scroll the system list under the pointer with the mouse wheel
+fn scroll_system_list(
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    hover_map: Res<HoverMap>,
+    parents: Query<&Parent>,
+    mut lists: Query<&mut ScrollPosition, With<SystemList>>,
+) {
+    for event in mouse_wheel_events.read() {
+        let dy = match event.unit {
+            MouseScrollUnit::Line => event.y * SCROLL_LINE_HEIGHT,
+            MouseScrollUnit::Pixel => event.y,
+        };
+        let hovered = hover_map.values().flat_map(|hits| hits.keys());
+        for &entity in hovered {
+            // The pointer is usually over one of the list's rows rather than the list itself
+            let list = std::iter::once(entity)
+                .chain(parents.iter_ancestors(entity))
+                .find(|&ancestor| lists.contains(ancestor));
+            if let Some(Ok(mut scroll_position)) = list.map(|list| lists.get_mut(list)) {
+                scroll_position.offset_y -= dy;
+            }
+        }
+    }
+}
+
+fn build_stepping_hint(mut commands: Commands, bindings: Res<InputBindings>) {
     let hint_text = if cfg!(feature = "bevy_debug_stepping") {
-        "Press ` to toggle stepping mode (S: step system, Space: step frame)"
+        format!(
+            "Press {} to toggle stepping mode ({}: step system, {}: step frame, \
+             {}/{}: select system, {}: breakpoint, {}: skip, {}: always run, \
+             or use the buttons of the panel)",
+            bindings.describe(Action::SteppingToggle),
+            bindings.describe(Action::SteppingStep),
+            bindings.describe(Action::SteppingContinue),
+            bindings.describe(Action::SteppingSelectPrevious),
+            bindings.describe(Action::SteppingSelectNext),
+            bindings.describe(Action::SteppingBreakpoint),
+            bindings.describe(Action::SteppingSkip),
+            bindings.describe(Action::SteppingAlwaysRun),
+        )
     } else {
-        "Bevy was compiled without stepping support. Run with `--features=bevy_debug_stepping` to enable stepping."
+        "Bevy was compiled without stepping support. Run with `--features=bevy_debug_stepping` to enable stepping.".to_string()
     };
     info!("{}", hint_text);
     // stepping description box
@@ -206,12 +867,12 @@ fn build_stepping_hint(mut commands: Commands) {
     ));
 }
 
-fn handle_input(keyboard_input: Res<ButtonInput<KeyCode>>, mut stepping: ResMut<Stepping>) {
-    if keyboard_input.just_pressed(KeyCode::Slash) {
+fn handle_input(actions: Res<Actions>, mut stepping: ResMut<Stepping>, mut state: ResMut<State>) {
+    if actions.just_pressed(Action::SteppingDump) {
         info!("{:#?}", stepping);
     }
     // grave key to toggle stepping mode for the FixedUpdate schedule
-    if keyboard_input.just_pressed(KeyCode::Backquote) {
+    if actions.just_pressed(Action::SteppingToggle) {
         if stepping.is_enabled() {
             stepping.disable();
             debug!("disabled stepping");
@@ -225,22 +886,71 @@ fn handle_input(keyboard_input: Res<ButtonInput<KeyCode>>, mut stepping: ResMut<
         return;
     }
 
-    // space key will step the remainder of this frame
-    if keyboard_input.just_pressed(KeyCode::Space) {
+    // continue key (C unless rebound) will step the remainder of this frame
+    if actions.just_pressed(Action::SteppingContinue) {
         debug!("continue");
         stepping.continue_frame();
-    } else if keyboard_input.just_pressed(KeyCode::KeyS) {
+    } else if actions.just_pressed(Action::SteppingStep) {
         debug!("stepping frame");
         stepping.step_frame();
     }
+
+    let count = state.systems.len();
+    if count == 0 {
+        return;
+    }
+    if actions.just_pressed(Action::SteppingSelectPrevious) {
+        state.selected = (state.selected + count - 1) % count;
+    } else if actions.just_pressed(Action::SteppingSelectNext) {
+        state.selected = (state.selected + 1) % count;
+    }
+
+    let selected = state.selected;
+    if actions.just_pressed(Action::SteppingBreakpoint) {
+        toggle_behavior(&mut stepping, &mut state, selected, SystemBehavior::Break);
+    } else if actions.just_pressed(Action::SteppingSkip) {
+        toggle_behavior(&mut stepping, &mut state, selected, SystemBehavior::Skip);
+    } else if actions.just_pressed(Action::SteppingAlwaysRun) {
+        toggle_behavior(
+            &mut stepping,
+            &mut state,
+            selected,
+            SystemBehavior::AlwaysRun,
+        );
+    }
+}
+
+/// Keep continuing until the system being run to is reached, then give it back
+/// whatever behavior it had
+fn run_to_system(mut stepping: ResMut<Stepping>, mut state: ResMut<State>) {
+    let Some(index) = state.run_to else {
+        return;
+    };
+    let Some(system) = state.systems.get(index) else {
+        // Gone from the stepped schedules since the run started
+        state.run_to = None;
+        return;
+    };
+    let reached = stepping.cursor() == Some((system.schedule, system.node));
+    if reached || !stepping.is_enabled() {
+        apply_behavior(&mut stepping, system);
+        state.run_to = None;
+    } else {
+        stepping.continue_frame();
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_ui(
     mut commands: Commands,
     state: Res<State>,
     stepping: Res<Stepping>,
+    frame: Res<FrameCount>,
     ui: Single<(Entity, &Visibility), With<SteppingUi>>,
-    mut writer: TextUiWriter,
+    mut headers: Query<&mut Text, (With<SteppingHeader>, Without<SystemCursor>)>,
+    mut cursors: Query<(&SystemCursor, &mut Text)>,
+    mut names: Query<(&SystemName, &mut TextColor)>,
+    mut buttons: Query<(&BehaviorButton, &mut BackgroundColor)>,
 ) {
     // ensure the UI is only visible when stepping is enabled
     let (ui, vis) = *ui;
@@ -259,17 +969,115 @@ fn update_ui(
         return;
     }
 
-    let Some((cursor_schedule, cursor_system)) = stepping.cursor() else {
-        // no cursor means stepping isn't enabled, so we're done here
-        return;
+    // no cursor means stepping isn't enabled, so there's no schedule to show
+    let cursor = stepping.cursor();
+    let header = match cursor {
+        Some((schedule, _)) => format!("Frame {}, in {schedule:?}", frame.0),
+        None => format!("Frame {}", frame.0),
     };
+    for mut text in &mut headers {
+        if text.0 != header {
+            text.0.clone_from(&header);
+        }
+    }
 
-    for (schedule, system, text_index) in &state.systems {
-        let mark = if &cursor_schedule == schedule && *system == cursor_system {
+    // Lines of a panel that is about to be rebuilt may no longer have a system
+    for (SystemCursor(index), mut text) in &mut cursors {
+        let Some(system) = state.systems.get(*index) else {
+            continue;
+        };
+        let mark = if cursor == Some((system.schedule, system.node)) {
             "-> "
         } else {
             "   "
         };
-        *writer.text(ui, *text_index) = mark.to_string();
+        if text.0 != mark {
+            text.0 = mark.to_string();
+        }
+    }
+
+    for (SystemName(index), mut color) in &mut names {
+        let selected = if *index == state.selected {
+            SELECTED_FONT_COLOR
+        } else {
+            FONT_COLOR
+        };
+        if color.0 != selected {
+            color.0 = selected;
+        }
+    }
+
+    for (button, mut color) in &mut buttons {
+        let behavior = state
+            .systems
+            .get(button.system)
+            .map(|system| system.behavior);
+        let active = if behavior == Some(button.behavior) {
+            BUTTON_ACTIVE_COLOR
+        } else {
+            BUTTON_COLOR
+        };
+        if color.0 != active {
+            color.0 = active;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_star_matches_any_run() {
+        assert!(glob_match("*_system", "mygame::move_system"));
+        assert!(glob_match("*_system", "_system"));
+        assert!(!glob_match("*_system", "mygame::move_systems"));
+
+        assert!(glob_match("mygame::*::apply", "mygame::powerup::apply"));
+        assert!(glob_match("mygame::*::apply", "mygame::::apply"));
+        assert!(!glob_match(
+            "mygame::*::apply",
+            "mygame::powerup::apply_all"
+        ));
+
+        assert!(glob_match("mygame::*", "mygame::powerup::apply"));
+        assert!(glob_match("mygame::*", "mygame::"));
+        assert!(!glob_match("mygame::*", "mygame"));
+    }
+
+    #[test]
+    fn glob_question_mark_matches_one_character() {
+        assert!(glob_match("level?", "level1"));
+        assert!(!glob_match("level?", "level"));
+        assert!(!glob_match("level?", "level12"));
+        assert!(glob_match("?", "x"));
+    }
+
+    #[test]
+    fn glob_empty_pattern_matches_only_empty_text() {
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "mygame::move_paddle"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn glob_literal_matches_exactly() {
+        assert!(glob_match("mygame::move_paddle", "mygame::move_paddle"));
+        assert!(!glob_match("mygame::move_paddle", "mygame::move_paddles"));
+        assert!(!glob_match("mygame::move_paddle", "mygame::move_paddl"));
+        assert!(!glob_match("move_paddle", "mygame::move_paddle"));
+    }
+
+    #[test]
+    fn default_filter_skips_only_bevy_systems() {
+        let filter = SystemFilter::default();
+        assert!(!filter.matches("bevy_ecs::event::event_update_system"));
+        assert!(filter.matches("mygame::check_for_collisions"));
+
+        let disabled = SystemFilter {
+            enabled: false,
+            ..default()
+        };
+        assert!(disabled.matches("bevy_ecs::event::event_update_system"));
     }
 }